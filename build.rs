@@ -10,11 +10,158 @@ fn main() {
         .unwrap();
 }
 
+#[cfg(all(target_os = "linux", feature = "precompress"))]
+include!("src/shared/precompressed_extensions.rs");
+
+/// Returns the `.br` and `.gz` variants of the resource |name| with the contents
+/// |data|, if it is a text asset and compressing it pays off.
+#[cfg(all(target_os = "linux", feature = "precompress"))]
+fn precompress(name: &str, data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    use std::io::Write;
+
+    let is_asset = name.rsplit_once('.').is_some_and(|(_, extension)| {
+        PRECOMPRESSED_EXTENSIONS
+            .iter()
+            .any(|candidate| extension.eq_ignore_ascii_case(candidate))
+    });
+    if !is_asset || data.len() < 1024 {
+        return Vec::new();
+    }
+
+    let mut brotli = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+        writer.write_all(data).unwrap();
+    }
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    gzip.write_all(data).unwrap();
+    let gzip = gzip.finish().unwrap();
+
+    [(".br", brotli), (".gz", gzip)]
+        .into_iter()
+        .filter(|(_, compressed)| compressed.len() < data.len())
+        .map(|(suffix, compressed)| (format!("{name}{suffix}"), compressed))
+        .collect()
+}
+
+#[cfg(all(target_os = "linux", not(feature = "precompress")))]
+fn precompress(_name: &str, _data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    Vec::new()
+}
+
+/// Writes `embedded_resources.rs` to OUT_DIR, listing every file below
+/// |resources_dir| together with its contents.
+#[cfg(target_os = "linux")]
+fn embed_resources(resources_dir: &std::path::Path, out_dir: &std::path::Path) {
+    use std::{fmt::Write, fs, path::Path};
+
+    fn collect(dir: &Path, prefix: &str, files: &mut Vec<(String, std::path::PathBuf)>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let name = format!("{prefix}{}", entry.file_name().to_str().unwrap());
+            if entry.file_type().unwrap().is_dir() {
+                collect(&entry.path(), &format!("{name}/"), files);
+            } else {
+                files.push((name, entry.path()));
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    if resources_dir.exists() {
+        collect(resources_dir, "", &mut files);
+    }
+    files.sort();
+
+    let precompressed_dir = out_dir.join("precompressed");
+    let mut code = String::from("pub static EMBEDDED_RESOURCES: &[(&str, &[u8])] = &[\n");
+    for (name, path) in files {
+        let path = fs::canonicalize(path).unwrap();
+        writeln!(
+            code,
+            "    ({name:?}, include_bytes!({:?})),",
+            path.to_str().unwrap()
+        )
+        .unwrap();
+
+        for (variant, data) in precompress(&name, &fs::read(&path).unwrap()) {
+            let variant_path = precompressed_dir.join(&variant);
+            fs::create_dir_all(variant_path.parent().unwrap()).unwrap();
+            fs::write(&variant_path, data).unwrap();
+            writeln!(
+                code,
+                "    ({variant:?}, include_bytes!({:?})),",
+                variant_path.to_str().unwrap()
+            )
+            .unwrap();
+        }
+    }
+    code.push_str("];\n");
+
+    fs::write(out_dir.join("embedded_resources.rs"), code).unwrap();
+}
+
+/// Packs every file below |resources_dir| into the zip archive |archive_path| and
+/// records its SHA-256 in the RESOURCE_ARCHIVE_SHA256 environment variable.
+#[cfg(all(target_os = "linux", feature = "resource-archive"))]
+fn create_resource_archive(resources_dir: &std::path::Path, archive_path: &std::path::Path) {
+    use sha2::{Digest, Sha256};
+    use std::{fs, io::Write, path::Path};
+    use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+    fn add_dir(zip: &mut ZipWriter<fs::File>, dir: &Path, prefix: &str) {
+        let mut entries = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for entry in entries {
+            let name = format!("{prefix}{}", entry.file_name().to_str().unwrap());
+            if entry.file_type().unwrap().is_dir() {
+                add_dir(zip, &entry.path(), &format!("{name}/"));
+            } else {
+                let data = fs::read(entry.path()).unwrap();
+                // Compressing the variants again doesn't pay off.
+                let stored = options.compression_method(CompressionMethod::Stored);
+                for (variant, compressed) in precompress(&name, &data) {
+                    zip.start_file(variant, stored).unwrap();
+                    zip.write_all(&compressed).unwrap();
+                }
+                zip.start_file(name, options).unwrap();
+                zip.write_all(&data).unwrap();
+            }
+        }
+    }
+
+    let mut zip = ZipWriter::new(fs::File::create(archive_path).unwrap());
+    if resources_dir.exists() {
+        add_dir(&mut zip, resources_dir, "");
+    }
+    zip.finish().unwrap();
+
+    let sha256 = Sha256::digest(fs::read(archive_path).unwrap());
+    let sha256: String = sha256.iter().map(|byte| format!("{byte:02x}")).collect();
+    println!("cargo::rustc-env=RESOURCE_ARCHIVE_SHA256={sha256}");
+}
+
 #[cfg(target_os = "linux")]
 fn main() {
     use std::{env, fs, path::Path};
 
     let out_dir = env::var("OUT_DIR").unwrap();
+    let resources_dir = Path::new("resources/linux");
+    println!("cargo::rerun-if-changed=resources/linux");
+    println!("cargo::rerun-if-changed=src/shared/precompressed_extensions.rs");
+
+    if env::var_os("CARGO_FEATURE_EMBED_RESOURCES").is_some() {
+        // The resources are served from memory, there is nothing to copy.
+        embed_resources(resources_dir, Path::new(&out_dir));
+        return;
+    }
+
     // OUT_DIR is something like target/debug/build/<pkg>/out
     // We need target/debug/
     let target_dir = Path::new(&out_dir)
@@ -22,21 +169,39 @@ fn main() {
         .nth(3)
         .expect("could not find target dir");
 
+    #[cfg(feature = "resource-archive")]
+    create_resource_archive(resources_dir, &target_dir.join("message_router.zip"));
+
     // Copy HTML resources to target/<profile>/files/
     let files_dir = target_dir.join("files");
     fs::create_dir_all(&files_dir).unwrap();
 
-    let resources_dir = Path::new("resources/linux");
+    let mut written = std::collections::HashSet::new();
     if resources_dir.exists() {
         for entry in fs::read_dir(resources_dir).unwrap() {
             let entry = entry.unwrap();
             if entry.file_type().unwrap().is_file() {
                 fs::copy(entry.path(), files_dir.join(entry.file_name())).unwrap();
+
+                let name = entry.file_name().to_str().unwrap().to_string();
+                for (variant, data) in precompress(&name, &fs::read(entry.path()).unwrap()) {
+                    fs::write(files_dir.join(&variant), data).unwrap();
+                    written.insert(variant);
+                }
+                written.insert(name);
             }
         }
     }
 
-    println!("cargo::rerun-if-changed=resources/linux");
+    // Remove variants of earlier builds, otherwise they would be served instead of
+    // changed assets or after the feature was turned off.
+    for entry in fs::read_dir(&files_dir).unwrap() {
+        let entry = entry.unwrap();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if (name.ends_with(".br") || name.ends_with(".gz")) && !written.contains(&name) {
+            fs::remove_file(entry.path()).unwrap();
+        }
+    }
 }
 
 #[cfg(not(any(target_os = "windows", target_os = "linux")))]
@@ -54,10 +54,13 @@ define_class! {
         /// already running.
         #[unsafe(method(applicationShouldHandleReopen:hasVisibleWindows:))]
         unsafe fn application_should_handle_reopen(&self, _sender: &NSApplication, _has_visible_windows: Bool) -> Bool {
-            if let Some(manager) = ClientManager::instance() {
+            // Prefer the active manager, falling back to any other one with a
+            // window left to show.
+            for manager in ClientManager::instance().into_iter().chain(ClientManager::instances()) {
                 let manager = manager.lock().expect("Failed to lock ClientManager");
-                if !manager.is_closing() {
+                if !manager.is_closing() && !manager.browser_list().is_empty() {
                     manager.show_main_window();
+                    break;
                 }
             }
             Bool::NO
@@ -164,8 +167,8 @@ define_class!(
         /// leading to it must be redirected.
         #[unsafe(method(terminate:))]
         unsafe fn terminate(&self, _sender: &AnyObject) {
-            if let Some(manager) = ClientManager::instance() {
-                let mut manager = manager.lock().expect("Failed to lock SimpleHandler");
+            for manager in ClientManager::instances() {
+                let mut manager = manager.lock().expect("Failed to lock ClientManager");
                 if !manager.is_closing() {
                     manager.close_all_browsers(false);
                 }
@@ -1,13 +1,188 @@
-use std::cell::RefCell;
+use std::{cell::RefCell, os::raw::c_int};
 
 use cef::*;
 
-use crate::shared::{client_impl::setup_client, resource_util::TEST_ORIGIN};
+use crate::shared::{
+    client_impl::setup_client,
+    profile::{DEFAULT_PROFILE, get_profile_request_context, is_valid_profile_name},
+    proxy_resource_handler::get_dev_server_url,
+    resource_mount::{MountSource, MountTable},
+    resource_util::TEST_ORIGIN,
+    resource_watcher::get_dev_resource_dir,
+    scheme::{APP_ORIGIN, register_custom_schemes, register_scheme_handler_factories},
+};
 
 fn get_startup_url() -> String {
     format!("{}{}", TEST_ORIGIN, "message_router.html")
 }
 
+/// Returns the resource mounts of the application.
+///
+/// Additional mounts can be added with `--resource-mount=<url prefix>=<directory>`,
+/// separating multiple mounts with `;`. `--spa-mount=<url prefix>` enables the
+/// single-page-app mode of a mount, serving `index.html` for client-side routes.
+/// `--dev-resources[=<directory>]` serves the app origins from the source tree.
+fn get_mount_table(command_line: &CommandLine) -> MountTable {
+    let mut mount_table = MountTable::with_default_mounts();
+    mount_table.mount(APP_ORIGIN, MountSource::Bundle(String::new()));
+
+    // Serve the application from the resource archive if there is one.
+    #[cfg(all(target_os = "linux", feature = "resource-archive"))]
+    match crate::shared::resource_archive::ArchiveProvider::open_default() {
+        Ok(archive) => {
+            let archive = MountSource::Provider(std::sync::Arc::new(archive));
+            mount_table.mount(TEST_ORIGIN, archive.clone());
+            mount_table.mount(APP_ORIGIN, archive);
+        }
+        Err(err) => eprintln!("failed to open the resource archive: {err:#}"),
+    }
+
+    // Serve the resources from the source tree during development.
+    if let Some(directory) = get_dev_resource_dir(command_line) {
+        mount_table.mount(TEST_ORIGIN, MountSource::Directory(directory.clone()));
+        mount_table.mount(APP_ORIGIN, MountSource::Directory(directory));
+    }
+
+    let switch = CefString::from("resource-mount");
+    if command_line.has_switch(Some(&switch)) != 0 {
+        let value = CefString::from(&command_line.switch_value(Some(&switch))).to_string();
+        for mount in value.split(';').filter(|mount| !mount.is_empty()) {
+            // Split at the last '=' since URL prefixes may contain one.
+            let Some((prefix, directory)) = mount.rsplit_once('=') else {
+                eprintln!("ignoring invalid resource mount {mount:?}");
+                continue;
+            };
+            mount_table.mount(prefix, MountSource::Directory(directory.into()));
+        }
+    }
+
+    // Forward the app origins to a dev server, e.g. for hot reload. The page keeps
+    // its logical origin so that the message router accepts its queries.
+    if let Some(dev_server_url) = get_dev_server_url(command_line) {
+        mount_table.mount(TEST_ORIGIN, MountSource::Proxy(dev_server_url.clone()));
+        mount_table.mount(APP_ORIGIN, MountSource::Proxy(dev_server_url));
+    }
+
+    let switch = CefString::from("spa-mount");
+    if command_line.has_switch(Some(&switch)) != 0 {
+        let value = CefString::from(&command_line.switch_value(Some(&switch))).to_string();
+        for prefix in value.split(';').filter(|prefix| !prefix.is_empty()) {
+            if !mount_table.set_spa_fallback(prefix, Some("index.html")) {
+                eprintln!("ignoring single-page-app mode of unknown mount {prefix:?}");
+            }
+        }
+    }
+
+    mount_table
+}
+
+/// Window options read from |command_line|.
+struct WindowOptions {
+    runtime_style: RuntimeStyle,
+    use_views: bool,
+    profile: String,
+}
+
+/// Returns the window options of |command_line|, or `None` if its profile is
+/// invalid.
+fn get_window_options(command_line: &CommandLine) -> Option<WindowOptions> {
+    // Check if Alloy style will be used.
+    let use_alloy_style = command_line.has_switch(Some(&CefString::from("use-alloy-style"))) != 0;
+    let runtime_style = if use_alloy_style {
+        RuntimeStyle::ALLOY
+    } else {
+        RuntimeStyle::DEFAULT
+    };
+
+    // Views is enabled by default (add `--use-native` to disable).
+    let use_views = command_line.has_switch(Some(&CefString::from("use-native"))) != 0;
+
+    // The profile of the window (add `--profile=<name>` to select one).
+    let profile_switch = CefString::from("profile");
+    let profile = if command_line.has_switch(Some(&profile_switch)) != 0 {
+        CefString::from(&command_line.switch_value(Some(&profile_switch))).to_string()
+    } else {
+        DEFAULT_PROFILE.to_string()
+    };
+    if profile != DEFAULT_PROFILE && !is_valid_profile_name(&profile) {
+        eprintln!("invalid profile {profile:?}, use lower case letters, digits, '-' and '_'");
+        return None;
+    }
+
+    Some(WindowOptions {
+        runtime_style,
+        use_views,
+        profile,
+    })
+}
+
+/// Creates a new top-level browser window for |url|.
+///
+/// |profile| selects the request context of the browser. Browsers of different
+/// profiles don't share cookies, cache or storage. No window is created if the
+/// profile is invalid, see `is_valid_profile_name`.
+pub fn create_browser_window(
+    mut client: Option<Client>,
+    url: &str,
+    profile: &str,
+    runtime_style: RuntimeStyle,
+    use_views: bool,
+) {
+    debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+    let mut request_context = if profile == DEFAULT_PROFILE {
+        None
+    } else {
+        // Never fall back to the default profile, that would mix the sessions.
+        let Some(request_context) = get_profile_request_context(profile) else {
+            eprintln!("failed to create the request context of profile {profile}");
+            return;
+        };
+        Some(request_context)
+    };
+
+    // Specify CEF browser settings here.
+    let settings = BrowserSettings::default();
+
+    // If using Views create the browser using the Views framework, otherwise
+    // create the browser using the native platform framework.
+    if use_views {
+        // Create the BrowserView.
+        let mut delegate = SimpleBrowserViewDelegate::new(runtime_style);
+        let browser_view = browser_view_create(
+            client.as_mut(),
+            Some(&url.into()),
+            Some(&settings),
+            None,
+            request_context.as_mut(),
+            Some(&mut delegate),
+        );
+
+        // Create the Window. It will show itself after creation.
+        let mut delegate =
+            SimpleWindowDelegate::new(RefCell::new(browser_view), runtime_style, ShowState::NORMAL);
+        window_create_top_level(Some(&mut delegate));
+    } else {
+        // Information used when creating the native window.
+        let window_info = WindowInfo {
+            runtime_style,
+            ..Default::default()
+        };
+
+        #[cfg(target_os = "windows")]
+        let window_info = window_info.set_as_popup(Default::default(), "cefsimple");
+
+        browser_host_create_browser(
+            Some(&window_info),
+            client.as_mut(),
+            Some(&url.into()),
+            Some(&settings),
+            None,
+            request_context.as_mut(),
+        );
+    }
+}
+
 wrap_app! {
     pub struct BrowserApp {
         browser_process_handler: BrowserProcessHandler,
@@ -26,6 +201,10 @@ wrap_app! {
             }
         }
 
+        fn on_register_custom_schemes(&self, registrar: Option<&mut SchemeRegistrar>) {
+            register_custom_schemes(registrar);
+        }
+
         fn browser_process_handler(&self) -> Option<BrowserProcessHandler> {
             Some(self.browser_process_handler.clone())
         }
@@ -42,67 +221,58 @@ wrap_browser_process_handler! {
             debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
             let startup_url = get_startup_url();
-
-            // Check if Alloy style will be used.
             let command_line = command_line_get_global().expect("Failed to get command line");
-            let use_alloy_style =
-                command_line.has_switch(Some(&CefString::from("use-alloy-style"))) != 0;
-            let runtime_style = if use_alloy_style {
-                RuntimeStyle::ALLOY
-            } else {
-                RuntimeStyle::DEFAULT
-            };
 
             // SimpleHandler implements browser-level callbacks.
-            self.client.replace(Some(setup_client(startup_url.clone())));
-            let mut client = self.default_client();
-
-            // Specify CEF browser settings here.
-            let settings = BrowserSettings::default();
-
-            // Views is enabled by default (add `--use-native` to disable).
-            let use_views = command_line.has_switch(Some(&CefString::from("use-native"))) != 0;
-
-            // If using Views create the browser using the Views framework, otherwise
-            // create the browser using the native platform framework.
-            if use_views {
-                // Create the BrowserView.
-                let mut delegate = SimpleBrowserViewDelegate::new(runtime_style);
-                let browser_view = browser_view_create(
-                    client.as_mut(),
-                    Some(&startup_url.as_str().into()),
-                    Some(&settings),
-                    None,
-                    None,
-                    Some(&mut delegate),
-                );
-
-                // Create the Window. It will show itself after creation.
-                let mut delegate = SimpleWindowDelegate::new(
-                    RefCell::new(browser_view),
-                    runtime_style,
-                    ShowState::NORMAL,
-                );
-                window_create_top_level(Some(&mut delegate));
-            } else {
-                // Information used when creating the native window.
-                let window_info = WindowInfo {
-                    runtime_style,
-                    ..Default::default()
-                };
-
-                #[cfg(target_os = "windows")]
-                let window_info = window_info.set_as_popup(Default::default(), "cefsimple");
-
-                browser_host_create_browser(
-                    Some(&window_info),
-                    client.as_mut(),
-                    Some(&startup_url.as_str().into()),
-                    Some(&settings),
-                    None,
-                    None,
-                );
+            // Serve the custom schemes from the same mounts as regular requests.
+            register_scheme_handler_factories(None);
+
+            let mount_table = get_mount_table(&command_line);
+            let (client, manager) = setup_client(startup_url.clone(), mount_table);
+            self.client.replace(Some(client));
+
+            // Reload the pages when their resources are edited (add `--dev-resources`).
+            if get_dev_resource_dir(&command_line).is_some() {
+                let mut manager = manager.lock().expect("Failed to lock ClientManager");
+                manager.watch_resources(true);
             }
+            let client = self.default_client();
+
+            let Some(options) = get_window_options(&command_line) else {
+                quit_message_loop();
+                return;
+            };
+            create_browser_window(
+                client,
+                &startup_url,
+                &options.profile,
+                options.runtime_style,
+                options.use_views,
+            );
+        }
+
+        fn on_already_running_app_relaunch(
+            &self,
+            command_line: Option<&mut CommandLine>,
+            _current_directory: Option<&CefString>,
+        ) -> c_int {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            // Another launch found this instance holding the profile root. Open a
+            // window for it instead of starting a second browser process.
+            let options = command_line.and_then(|command_line| get_window_options(command_line));
+            let Some(options) = options else {
+                // Handled, the invalid profile was reported.
+                return 1;
+            };
+            create_browser_window(
+                self.default_client(),
+                &get_startup_url(),
+                &options.profile,
+                options.runtime_style,
+                options.use_views,
+            );
+            1
         }
 
         fn default_client(&self) -> Option<Client> {
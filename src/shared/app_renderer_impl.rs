@@ -8,12 +8,18 @@ use cef::{
     *,
 };
 
+use crate::shared::scheme::register_custom_schemes;
+
 wrap_app! {
     pub struct RendererApp {
         render_process_handler: RenderProcessHandler,
     }
 
     impl App {
+        fn on_register_custom_schemes(&self, registrar: Option<&mut SchemeRegistrar>) {
+            register_custom_schemes(registrar);
+        }
+
         fn render_process_handler(&self) -> Option<RenderProcessHandler> {
             Some(self.render_process_handler.clone())
         }
@@ -0,0 +1,206 @@
+use std::{collections::HashMap, sync::Arc};
+
+/// Page state of a browser, kept up to date from the display and load handlers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrowserState {
+    pub title: String,
+    pub url: String,
+    pub favicon_urls: Vec<String>,
+    pub is_loading: bool,
+    /// Loading progress between 0.0 and 1.0.
+    pub loading_progress: f64,
+    pub can_go_back: bool,
+    pub can_go_forward: bool,
+    pub is_fullscreen: bool,
+    /// The profile the browser was created with, see `profile::get_profile_name`.
+    pub profile: String,
+}
+
+/// Called with the browser identifier and its new state after every change, and
+/// with `None` once the browser closed.
+pub type BrowserStateListener = Arc<dyn Fn(i32, Option<&BrowserState>) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+/// A change of the state of a browser, together with the listeners to notify.
+/// Returned by `BrowserStateStore` so that the listeners can be called after the
+/// store was unlocked.
+pub struct BrowserStateChange {
+    browser_id: i32,
+    state: Option<BrowserState>,
+    listeners: Vec<BrowserStateListener>,
+}
+
+impl BrowserStateChange {
+    pub fn notify(&self) {
+        for listener in &self.listeners {
+            listener(self.browser_id, self.state.as_ref());
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BrowserStateStore {
+    states: HashMap<i32, BrowserState>,
+    listeners: Vec<(SubscriptionId, BrowserStateListener)>,
+    next_subscription_id: usize,
+}
+
+impl BrowserStateStore {
+    pub fn get(&self, browser_id: i32) -> Option<&BrowserState> {
+        self.states.get(&browser_id)
+    }
+
+    /// Applies |update| to the state of |browser_id|. Returns the change to notify
+    /// the listeners of if anything changed.
+    #[must_use]
+    pub fn update(
+        &mut self,
+        browser_id: i32,
+        update: impl FnOnce(&mut BrowserState),
+    ) -> Option<BrowserStateChange> {
+        let state = self.states.entry(browser_id).or_default();
+        let old_state = state.clone();
+        update(state);
+
+        if *state == old_state {
+            return None;
+        }
+        let state = state.clone();
+        self.change(browser_id, Some(state))
+    }
+
+    /// Forgets the state of the closed browser |browser_id|. Returns the removal
+    /// to notify the listeners of if there was a state.
+    #[must_use]
+    pub fn remove(&mut self, browser_id: i32) -> Option<BrowserStateChange> {
+        self.states.remove(&browser_id)?;
+        self.change(browser_id, None)
+    }
+
+    fn change(&self, browser_id: i32, state: Option<BrowserState>) -> Option<BrowserStateChange> {
+        (!self.listeners.is_empty()).then(|| BrowserStateChange {
+            browser_id,
+            state,
+            listeners: self
+                .listeners
+                .iter()
+                .map(|(_, listener)| listener.clone())
+                .collect(),
+        })
+    }
+
+    pub fn subscribe(&mut self, listener: BrowserStateListener) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription_id);
+        self.next_subscription_id += 1;
+        self.listeners.push((id, listener));
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.listeners.len();
+        self.listeners.retain(|(item, _)| *item != id);
+        self.listeners.len() != len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    type Events = Arc<Mutex<Vec<(i32, Option<String>)>>>;
+
+    /// Returns a listener recording the titles it is called with.
+    fn recorder(events: &Events) -> BrowserStateListener {
+        let events = events.clone();
+        Arc::new(move |browser_id, state| {
+            events
+                .lock()
+                .unwrap()
+                .push((browser_id, state.map(|state| state.title.clone())));
+        })
+    }
+
+    fn title(title: &str) -> impl FnOnce(&mut BrowserState) {
+        move |state| state.title = title.to_string()
+    }
+
+    #[test]
+    fn changes_are_notified() {
+        let events = Events::default();
+        let mut store = BrowserStateStore::default();
+        store.subscribe(recorder(&events));
+
+        store.update(1, title("first")).unwrap().notify();
+        assert!(store.update(1, title("first")).is_none());
+        store.update(2, title("second")).unwrap().notify();
+
+        assert_eq!(
+            store.get(1).map(|state| state.title.as_str()),
+            Some("first")
+        );
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                (1, Some("first".to_string())),
+                (2, Some("second".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn removal_is_notified() {
+        let events = Events::default();
+        let mut store = BrowserStateStore::default();
+        store.subscribe(recorder(&events));
+
+        store.update(1, title("page")).unwrap().notify();
+        store.remove(1).unwrap().notify();
+        assert!(store.remove(1).is_none());
+
+        assert!(store.get(1).is_none());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [(1, Some("page".to_string())), (1, None)]
+        );
+    }
+
+    #[test]
+    fn unsubscribe() {
+        let events = Events::default();
+        let mut store = BrowserStateStore::default();
+        let id = store.subscribe(recorder(&events));
+
+        assert!(store.unsubscribe(id));
+        assert!(!store.unsubscribe(id));
+        assert!(store.update(1, title("page")).is_none());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn listeners_may_lock_the_store() {
+        let store = Arc::new(Mutex::new(BrowserStateStore::default()));
+        let titles = Arc::new(Mutex::new(Vec::new()));
+        let listener_store = store.clone();
+        let listener_titles = titles.clone();
+        store
+            .lock()
+            .unwrap()
+            .subscribe(Arc::new(move |browser_id, _| {
+                let title = listener_store
+                    .lock()
+                    .unwrap()
+                    .get(browser_id)
+                    .map(|state| state.title.clone());
+                listener_titles.lock().unwrap().push(title);
+            }));
+
+        let change = store.lock().unwrap().update(1, title("page"));
+        change.unwrap().notify();
+
+        assert_eq!(*titles.lock().unwrap(), [Some("page".to_string())]);
+    }
+}
@@ -1,4 +1,7 @@
-use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::sync::{
+    Arc, Mutex, Weak,
+    atomic::{AtomicUsize, Ordering},
+};
 
 use cef::{
     wrapper::message_router::{
@@ -9,11 +12,42 @@ use cef::{
 };
 
 use crate::shared::{
+    browser_state::{
+        BrowserState, BrowserStateChange, BrowserStateListener, BrowserStateStore, SubscriptionId,
+    },
+    certificate_policy::{
+        CertificateDecision, CertificateLog, CertificatePolicy, certificate_error_page_html,
+        get_certificate_fingerprints, get_host,
+    },
+    crash_recovery::{
+        CrashAction, CrashCallback, CrashInfo, CrashRecoveryPolicy, CrashTracker, crash_page_html,
+    },
+    download_manager::{DownloadManager, DownloadMessageHandler, DownloadPolicy, DownloadRecord},
+    dynamic_content::{ContentHandler, ContentRoutes, create_content_resource_handler},
+    local_resource_handler::{LocalResponse, create_local_resource_handler},
+    navigation_policy::{
+        ExternalOpener, NavigationDecision, NavigationInfo, NavigationPolicy, RedirectTracker,
+        default_external_opener,
+    },
+    permission_manager::{
+        PermissionManager, PermissionMessageHandler, PermissionPolicy, PermissionType,
+    },
     platform::{platform_show_window, platform_title_change},
-    resource_util::{get_resource_handler, get_resource_path},
+    profile::{DEFAULT_PROFILE, get_profile_name},
+    request_rules::{ContinueRequest, RequestRules, RuleAction},
+    resource_mount::{MountSource, MountTable},
+    resource_pipeline::ResourcePipeline,
+    resource_util::{
+        CERTIFICATE_ERROR_PAGE_PATH, CRASH_PAGE_PATH, TEST_ORIGIN, get_data_resource_handler,
+        get_post_data_bytes, get_resource_path, normalize_resource_path,
+    },
+    resource_watcher::{RESOURCE_WATCH_INTERVAL, ResourceWatcher},
+    response_filter::{ResponseFilters, StreamFilterFactory},
+    security_policy::{CSP_REPORT_PATH, CspReport, CspReportCallback},
 };
 
 const TEST_MESSAGE_NAME: &str = "MessageRouterTest";
+const PROFILE_MESSAGE_NAME: &str = "GetProfileName";
 
 #[derive(Debug)]
 pub struct MessageHandler {
@@ -24,7 +58,7 @@ impl BrowserSideHandler for MessageHandler {
     // Called due to cefQuery execution in message_router.html.
     fn on_query_str(
         &self,
-        _browser: Option<Browser>,
+        browser: Option<Browser>,
         frame: Option<Frame>,
         _query_id: i64,
         request: &str,
@@ -54,10 +88,38 @@ impl BrowserSideHandler for MessageHandler {
             return true;
         }
 
+        if message_name == PROFILE_MESSAGE_NAME {
+            // Return the profile the querying browser was created with.
+            let profile = browser.as_ref().map(get_profile_name).unwrap_or_default();
+
+            callback
+                .lock()
+                .expect("Failed to lock callback")
+                .success_str(&profile);
+
+            return true;
+        }
+
         false
     }
 }
 
+/// The purpose of a browser in `ClientManager::browser_list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrowserRole {
+    /// A regular application window.
+    App,
+    /// A devtools window inspecting another browser. DevTools windows neither keep
+    /// the application alive nor count as the main window.
+    DevTools { inspected_browser_id: i32 },
+}
+
+#[derive(Clone)]
+pub struct BrowserEntry {
+    pub browser: Browser,
+    pub role: BrowserRole,
+}
+
 pub struct ClientManager {
     weak_self: Weak<Mutex<ClientManager>>,
 
@@ -65,25 +127,98 @@ pub struct ClientManager {
     browser_ct: usize,
     message_router: Option<Arc<BrowserSideRouter>>,
     message_handler_id: Option<HandlerId>,
+    download_handler_id: Option<HandlerId>,
+    permission_handler_id: Option<HandlerId>,
 
-    browser_list: Vec<Browser>,
+    browser_list: Vec<BrowserEntry>,
     is_closing: bool,
+
+    crash_policy: CrashRecoveryPolicy,
+    crash_tracker: CrashTracker,
+    crash_callback: Option<CrashCallback>,
+
+    csp_report_callback: Option<CspReportCallback>,
+
+    navigation_policy: NavigationPolicy,
+    external_opener: ExternalOpener,
+    redirect_tracker: RedirectTracker,
+
+    certificate_policy: CertificatePolicy,
+    certificate_log: CertificateLog,
+
+    // Shared with the message router handler for the download queries.
+    downloads: Arc<Mutex<DownloadManager>>,
+    // Shared with the message router handler for the permission queries.
+    permissions: Arc<Mutex<PermissionManager>>,
+
+    content_routes: ContentRoutes,
+    request_rules: RequestRules,
+    response_filters: ResponseFilters,
+
+    browser_states: BrowserStateStore,
+
+    mount_table: MountTable,
+    resource_pipeline: Arc<ResourcePipeline>,
+    resource_watcher: Option<ResourceWatcher>,
 }
 
-static CLIENT_MANAGER_INSTANCE: OnceLock<Weak<Mutex<ClientManager>>> = OnceLock::new();
+static CLIENT_MANAGER_INSTANCES: Mutex<Vec<Weak<Mutex<ClientManager>>>> = Mutex::new(Vec::new());
+static ACTIVE_CLIENT_MANAGER: Mutex<Option<Weak<Mutex<ClientManager>>>> = Mutex::new(None);
+// Browsers of all managers. The message loop quits when this reaches zero.
+static OPEN_BROWSER_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 impl ClientManager {
+    /// Returns the manager of the most recently created browser, see
+    /// `ClientManager::set_active`. Returns `None` before the first browser was
+    /// created; use the manager returned by `setup_client` to configure it.
     pub fn instance() -> Option<Arc<Mutex<Self>>> {
-        CLIENT_MANAGER_INSTANCE
-            .get()
-            .and_then(|manager| manager.upgrade())
+        ACTIVE_CLIENT_MANAGER
+            .lock()
+            .expect("Failed to lock ACTIVE_CLIENT_MANAGER")
+            .as_ref()
+            .and_then(Weak::upgrade)
+    }
+
+    /// Returns all managers that are still alive, oldest first.
+    pub fn instances() -> Vec<Arc<Mutex<Self>>> {
+        let mut instances = CLIENT_MANAGER_INSTANCES
+            .lock()
+            .expect("Failed to lock CLIENT_MANAGER_INSTANCES");
+        instances.retain(|manager| manager.strong_count() > 0);
+        instances.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// Returns the manager that owns |browser|.
+    ///
+    /// This locks every manager in turn, so it must not be called while holding the
+    /// lock of any `ClientManager`.
+    pub fn for_browser(browser: &Browser) -> Option<Arc<Mutex<Self>>> {
+        let browser_id = browser.identifier();
+        Self::instances().into_iter().find(|manager| {
+            manager
+                .lock()
+                .expect("Failed to lock ClientManager")
+                .browser_list
+                .iter()
+                .any(|entry| entry.browser.identifier() == browser_id)
+        })
+    }
+
+    /// Makes this manager the one returned by `ClientManager::instance`, which is
+    /// used by global entry points such as the macOS dock reopen hook.
+    pub fn set_active(&self) {
+        ACTIVE_CLIENT_MANAGER
+            .lock()
+            .expect("Failed to lock ACTIVE_CLIENT_MANAGER")
+            .replace(self.weak_self.clone());
     }
 
-    pub fn new(startup_url: String) -> Arc<Mutex<Self>> {
+    pub fn new(startup_url: String, mount_table: MountTable) -> Arc<Mutex<Self>> {
         Arc::new_cyclic(|weak_self| {
-            if let Err(instance) = CLIENT_MANAGER_INSTANCE.set(weak_self.clone()) {
-                assert_eq!(instance.strong_count(), 0, "Replacing a viable instance");
-            }
+            CLIENT_MANAGER_INSTANCES
+                .lock()
+                .expect("Failed to lock CLIENT_MANAGER_INSTANCES")
+                .push(weak_self.clone());
 
             Mutex::new(Self {
                 weak_self: weak_self.clone(),
@@ -91,8 +226,35 @@ impl ClientManager {
                 browser_ct: 0,
                 message_router: None,
                 message_handler_id: None,
+                download_handler_id: None,
+                permission_handler_id: None,
                 browser_list: Vec::new(),
                 is_closing: false,
+                crash_policy: CrashRecoveryPolicy::default(),
+                crash_tracker: CrashTracker::default(),
+                crash_callback: None,
+                csp_report_callback: None,
+                navigation_policy: NavigationPolicy {
+                    mounted_origins: mount_table.prefixes(),
+                    ..Default::default()
+                },
+                external_opener: default_external_opener(),
+                redirect_tracker: RedirectTracker::default(),
+                certificate_policy: CertificatePolicy::default()
+                    .with_interstitial(true)
+                    .with_log_decisions(true),
+                certificate_log: CertificateLog::default(),
+                downloads: Arc::new(Mutex::new(DownloadManager::new(DownloadPolicy::default()))),
+                permissions: Arc::new(Mutex::new(PermissionManager::new(
+                    PermissionPolicy::default(),
+                ))),
+                content_routes: ContentRoutes::default(),
+                request_rules: RequestRules::default(),
+                response_filters: ResponseFilters::default(),
+                browser_states: BrowserStateStore::default(),
+                resource_pipeline: Arc::new(ResourcePipeline::new(&mount_table)),
+                mount_table,
+                resource_watcher: None,
             })
         })
     }
@@ -101,6 +263,233 @@ impl ClientManager {
         self.is_closing
     }
 
+    pub fn mount_table(&self) -> &MountTable {
+        &self.mount_table
+    }
+
+    /// Replaces the resource mounts consulted by `ClientManager::resource_handler`.
+    /// Providers and URL filters added to the pipeline are kept.
+    pub fn set_mount_table(&mut self, mount_table: MountTable) {
+        self.resource_pipeline.set_mounts(&mount_table);
+        self.navigation_policy.mounted_origins = mount_table.prefixes();
+        self.mount_table = mount_table;
+
+        if self.resource_watcher.is_some() {
+            self.watch_resources(true);
+        }
+    }
+
+    /// Watches the directory mounts for changes and reloads the browsers showing
+    /// a page of a changed mount, e.g. for editing resources during development.
+    pub fn watch_resources(&mut self, enable: bool) {
+        // Stop the current watcher first.
+        self.resource_watcher = None;
+        if !enable {
+            return;
+        }
+
+        let directories = self
+            .mount_table
+            .mounts()
+            .iter()
+            .filter_map(|mount| match &mount.source {
+                MountSource::Directory(directory) => {
+                    Some((mount.prefix.clone(), directory.clone()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if directories.is_empty() {
+            return;
+        }
+
+        let weak_self = self.weak_self.clone();
+        self.resource_watcher = Some(ResourceWatcher::start(
+            directories,
+            RESOURCE_WATCH_INTERVAL,
+            move |prefixes| {
+                let Some(this) = weak_self.upgrade() else {
+                    return;
+                };
+                let mut task = ReloadMounts::new(this, prefixes);
+                post_task(ThreadId::UI, Some(&mut task));
+            },
+        ));
+    }
+
+    /// Reloads the browsers whose main frame shows a page below one of |prefixes|,
+    /// bypassing the cache.
+    pub fn reload_mounts(&self, prefixes: &[String]) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        for entry in &self.browser_list {
+            if entry.role != BrowserRole::App {
+                continue;
+            }
+            let Some(frame) = entry.browser.main_frame() else {
+                continue;
+            };
+            let url = CefString::from(&frame.url()).to_string();
+            // The longest matching prefix owns the page.
+            if self
+                .mount_table
+                .resolve(&url)
+                .is_some_and(|(mount, _)| prefixes.contains(&mount.prefix))
+            {
+                entry.browser.reload_ignore_cache();
+            }
+        }
+    }
+
+    pub fn resource_pipeline(&self) -> Arc<ResourcePipeline> {
+        self.resource_pipeline.clone()
+    }
+
+    pub fn set_crash_policy(&mut self, policy: CrashRecoveryPolicy) {
+        self.crash_policy = policy;
+    }
+
+    /// Registers a callback that is notified about every render process crash,
+    /// e.g. for telemetry. It is called in a task on the UI thread, after this
+    /// manager was unlocked.
+    pub fn set_crash_callback(&mut self, callback: Option<CrashCallback>) {
+        self.crash_callback = callback;
+    }
+
+    /// Registers a callback that receives the Content Security Policy violation
+    /// reports of mounts with a report-only `SecurityPolicy`, on the UI thread.
+    pub fn set_csp_report_callback(&mut self, callback: Option<CspReportCallback>) {
+        self.csp_report_callback = callback;
+    }
+
+    /// Replaces the navigation policy. Its |mounted_origins| are taken from the
+    /// mount table.
+    pub fn set_navigation_policy(&mut self, mut policy: NavigationPolicy) {
+        policy.mounted_origins = self.mount_table.prefixes();
+        self.navigation_policy = policy;
+    }
+
+    /// Replaces the opener used for links that leave the allowed origins, e.g. to
+    /// confirm with the user first.
+    pub fn set_external_opener(&mut self, opener: ExternalOpener) {
+        self.external_opener = opener;
+    }
+
+    /// Replaces the policy deciding which certificate errors are ignored. By
+    /// default every certificate error cancels the request.
+    pub fn set_certificate_policy(&mut self, policy: CertificatePolicy) {
+        self.certificate_policy = policy;
+    }
+
+    /// Returns the recent certificate decisions, oldest first.
+    pub fn certificate_decisions(&self) -> Vec<CertificateDecision> {
+        self.certificate_log.decisions()
+    }
+
+    /// Replaces the policy for downloads started from now on.
+    pub fn set_download_policy(&self, policy: DownloadPolicy) {
+        self.downloads
+            .lock()
+            .expect("Failed to lock downloads")
+            .set_policy(policy);
+    }
+
+    /// Returns the running downloads of all browsers.
+    pub fn active_downloads(&self) -> Vec<DownloadRecord> {
+        self.downloads
+            .lock()
+            .expect("Failed to lock downloads")
+            .active_downloads()
+    }
+
+    /// Returns the last finished downloads of |profile|, oldest first.
+    pub fn download_history(&self, profile: &str) -> Vec<DownloadRecord> {
+        self.downloads
+            .lock()
+            .expect("Failed to lock downloads")
+            .history(profile)
+    }
+
+    /// Replaces the policy deciding the permission requests of pages, and loads
+    /// the decisions stored by it.
+    pub fn set_permission_policy(&self, policy: PermissionPolicy) {
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .set_policy(policy);
+    }
+
+    /// Returns the decisions the user asked to remember in |profile| as
+    /// `(origin, permission, allowed)`.
+    pub fn stored_permissions(&self, profile: &str) -> Vec<(String, PermissionType, bool)> {
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .stored_decisions(profile)
+    }
+
+    /// Stores the decision for |permission| of |origin| in |profile|, or forgets
+    /// it if |allowed| is `None`, e.g. for a settings page.
+    pub fn set_stored_permission(
+        &self,
+        profile: &str,
+        origin: &str,
+        permission: PermissionType,
+        allowed: Option<bool>,
+    ) {
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .set_stored_decision(profile, origin, permission, allowed);
+    }
+
+    /// Answers requests to |url| with the response of |handler|, e.g. for
+    /// `fetch` or `<img src>` on the app origin. |method| restricts the route to
+    /// one method. |url| excludes the query; a trailing `*` matches any URL
+    /// starting with the rest of |url|.
+    pub fn add_content_route(&mut self, method: Option<&str>, url: &str, handler: ContentHandler) {
+        self.content_routes.add(method, url, handler);
+    }
+
+    /// Removes the route added with |method| and |url|.
+    pub fn remove_content_route(&mut self, method: Option<&str>, url: &str) -> bool {
+        self.content_routes.remove(method, url)
+    }
+
+    /// Returns the content route handler answering |request|, if any. Requests
+    /// answered by content routes bypass the request rules and the resource
+    /// pipeline.
+    pub fn content_handler(&self, request: Option<&Request>) -> Option<ContentHandler> {
+        self.content_routes.find(request?)
+    }
+
+    /// Applies |action| to requests whose URL matches |pattern|, see
+    /// `RequestRules`. Requests answered by content routes and the crash page
+    /// bypass the rules.
+    pub fn add_request_rule(&mut self, pattern: &str, action: RuleAction) {
+        self.request_rules.add(pattern, action);
+    }
+
+    pub fn remove_request_rule(&mut self, pattern: &str) -> bool {
+        self.request_rules.remove(pattern)
+    }
+
+    /// Filters the bodies of responses to URLs matching |pattern| whose mime type
+    /// is one of |mime_types| (any if empty), e.g. with
+    /// `response_filter::inject_script`.
+    pub fn add_response_filter(
+        &mut self,
+        pattern: &str,
+        mime_types: &[&str],
+        factory: StreamFilterFactory,
+    ) {
+        self.response_filters.add(pattern, mime_types, factory);
+    }
+
+    pub fn remove_response_filter(&mut self, pattern: &str) -> bool {
+        self.response_filters.remove(pattern)
+    }
+
     pub fn show_main_window(&self) {
         let thread_id = ThreadId::UI;
         if currently_on(thread_id) == 0 {
@@ -114,7 +503,12 @@ impl ClientManager {
             return;
         }
 
-        let Some(mut main_browser) = self.browser_list.first().cloned() else {
+        let Some(mut main_browser) = self
+            .browser_list
+            .iter()
+            .find(|entry| entry.role == BrowserRole::App)
+            .map(|entry| entry.browser.clone())
+        else {
             return;
         };
 
@@ -141,16 +535,187 @@ impl ClientManager {
             return;
         }
 
-        for browser in self.browser_list.iter() {
-            let browser_host = browser.host().expect("BrowserHost is None");
+        for entry in self.browser_list.iter() {
+            let browser_host = entry.browser.host().expect("BrowserHost is None");
             browser_host.close_browser(force_close.into());
         }
     }
 
+    pub fn browser_list(&self) -> &[BrowserEntry] {
+        &self.browser_list
+    }
+
+    fn find_browser(&self, browser_id: i32) -> Option<&BrowserEntry> {
+        self.browser_list
+            .iter()
+            .find(|entry| entry.browser.identifier() == browser_id)
+    }
+
+    pub fn browser_state(&self, browser_id: i32) -> Option<BrowserState> {
+        self.browser_states.get(browser_id).cloned()
+    }
+
+    /// Registers |listener| to be called whenever the state of any browser changes,
+    /// and once more with `None` when the browser closed.
+    ///
+    /// Listeners are called in a task on the UI thread, never while this manager is
+    /// locked, so they may call into it.
+    pub fn subscribe_browser_state(&mut self, listener: BrowserStateListener) -> SubscriptionId {
+        self.browser_states.subscribe(listener)
+    }
+
+    pub fn unsubscribe_browser_state(&mut self, id: SubscriptionId) -> bool {
+        self.browser_states.unsubscribe(id)
+    }
+
+    fn update_browser_state(&mut self, browser_id: i32, update: impl FnOnce(&mut BrowserState)) {
+        let change = self.browser_states.update(browser_id, update);
+        notify_browser_state(change);
+    }
+
+    /// Opens the devtools of the browser |browser_id|, optionally inspecting the
+    /// element at |inspect_at|.
+    pub fn show_devtools(&mut self, browser_id: i32, inspect_at: Option<Point>) {
+        let thread_id = ThreadId::UI;
+        if currently_on(thread_id) == 0 {
+            // Execute on the UI thread.
+            let this = self
+                .weak_self
+                .upgrade()
+                .expect("Weak reference to ClientManager is None");
+            let mut task = ShowDevTools::new(this, browser_id, inspect_at);
+            post_task(thread_id, Some(&mut task));
+            return;
+        }
+
+        let Some(entry) = self.find_browser(browser_id) else {
+            return;
+        };
+        if entry.role != BrowserRole::App {
+            // Don't open devtools for devtools.
+            return;
+        }
+        let Some(browser_host) = entry.browser.host() else {
+            return;
+        };
+
+        if browser_host.has_dev_tools() != 0 && inspect_at.is_none() {
+            // Bring the existing devtools window to the front.
+            let devtools = self.browser_list.iter().find(|entry| {
+                entry.role
+                    == BrowserRole::DevTools {
+                        inspected_browser_id: browser_id,
+                    }
+            });
+            if let Some(mut devtools) = devtools.map(|entry| entry.browser.clone()) {
+                if let Some(window) =
+                    browser_view_get_for_browser(Some(&mut devtools)).and_then(|view| view.window())
+                {
+                    window.show();
+                } else {
+                    platform_show_window(Some(&mut devtools));
+                }
+            }
+            return;
+        }
+
+        // The devtools get their own client so that their browser is recognized in
+        // `on_after_created`.
+        let manager = self
+            .weak_self
+            .upgrade()
+            .expect("Weak reference to ClientManager is None");
+        let client = create_client(manager, Some(browser_id));
+
+        // Creating the devtools browser calls back into this manager, so defer it.
+        let mut task = OpenDevTools::new(browser_host, client, inspect_at);
+        post_task(ThreadId::UI, Some(&mut task));
+    }
+
+    /// Closes the devtools of the browser |browser_id|, if any.
+    pub fn close_devtools(&self, browser_id: i32) {
+        let Some(entry) = self.find_browser(browser_id) else {
+            return;
+        };
+
+        // Closing may synchronously call back into this manager, so defer it.
+        let mut task = CloseDevTools::new(entry.browser.clone());
+        post_task(ThreadId::UI, Some(&mut task));
+    }
+
+    /// Opens the devtools of |browser_id| or closes them if they are already open.
+    pub fn toggle_devtools(&mut self, browser_id: i32) {
+        let has_devtools = self
+            .find_browser(browser_id)
+            .and_then(|entry| entry.browser.host())
+            .is_some_and(|host| host.has_dev_tools() != 0);
+
+        if has_devtools {
+            self.close_devtools(browser_id);
+        } else {
+            self.show_devtools(browser_id, None);
+        }
+    }
+
+    // CefKeyboardHandler method
+    pub fn on_pre_key_event(&mut self, browser: Option<Browser>, event: &KeyEvent) -> bool {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        // Values from cef_event_flags_t and the Windows virtual key codes.
+        const EVENTFLAG_SHIFT_DOWN: u32 = 1 << 1;
+        const EVENTFLAG_CONTROL_DOWN: u32 = 1 << 2;
+        #[cfg(target_os = "macos")]
+        const EVENTFLAG_ALT_DOWN: u32 = 1 << 3;
+        #[cfg(target_os = "macos")]
+        const EVENTFLAG_COMMAND_DOWN: u32 = 1 << 7;
+        const VKEY_I: i32 = 0x49;
+        const VKEY_F12: i32 = 0x7B;
+
+        if event.type_ != KeyEventType::RAWKEYDOWN {
+            return false;
+        }
+        let Some(browser) = browser else {
+            return false;
+        };
+
+        let modifiers = event.modifiers;
+        let ctrl_shift = EVENTFLAG_CONTROL_DOWN | EVENTFLAG_SHIFT_DOWN;
+        let is_shortcut = event.windows_key_code == VKEY_F12
+            || (event.windows_key_code == VKEY_I && modifiers & ctrl_shift == ctrl_shift);
+        #[cfg(target_os = "macos")]
+        let is_shortcut = is_shortcut || {
+            let cmd_alt = EVENTFLAG_COMMAND_DOWN | EVENTFLAG_ALT_DOWN;
+            event.windows_key_code == VKEY_I && modifiers & cmd_alt == cmd_alt
+        };
+        if !is_shortcut {
+            return false;
+        }
+
+        let browser_id = match self
+            .find_browser(browser.identifier())
+            .map(|entry| entry.role)
+        {
+            Some(BrowserRole::App) => browser.identifier(),
+            // The shortcut in a devtools window closes it.
+            Some(BrowserRole::DevTools {
+                inspected_browser_id,
+            }) => inspected_browser_id,
+            None => return false,
+        };
+        self.toggle_devtools(browser_id);
+
+        true
+    }
+
     // CefDisplayHandler method
-    pub fn on_title_change(&self, mut browser: Option<Browser>, title: Option<&CefString>) {
+    pub fn on_title_change(&mut self, mut browser: Option<Browser>, title: Option<&CefString>) {
         debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
+        if let Some(browser) = browser.as_ref() {
+            let title = title.map(CefString::to_string).unwrap_or_default();
+            self.update_browser_state(browser.identifier(), |state| state.title = title);
+        }
+
         let browser_view = browser_view_get_for_browser(browser.as_mut());
         if let Some(browser_view) = browser_view {
             if let Some(window) = browser_view.window() {
@@ -161,6 +726,107 @@ impl ClientManager {
         }
     }
 
+    // CefDisplayHandler method
+    pub fn on_address_change(
+        &mut self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        url: Option<&CefString>,
+    ) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        let (Some(browser), Some(frame)) = (browser, frame) else {
+            return;
+        };
+        if frame.is_main() == 0 {
+            return;
+        }
+
+        let url = url.map(CefString::to_string).unwrap_or_default();
+        self.update_browser_state(browser.identifier(), |state| state.url = url);
+    }
+
+    // CefDisplayHandler method
+    pub fn on_favicon_urlchange(&mut self, browser: Option<Browser>, favicon_urls: Vec<String>) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        if let Some(browser) = browser {
+            self.update_browser_state(browser.identifier(), |state| {
+                state.favicon_urls = favicon_urls
+            });
+        }
+    }
+
+    // CefDisplayHandler method
+    pub fn on_fullscreen_mode_change(&mut self, browser: Option<Browser>, fullscreen: bool) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        if let Some(browser) = browser {
+            self.update_browser_state(browser.identifier(), |state| {
+                state.is_fullscreen = fullscreen
+            });
+        }
+    }
+
+    // CefDisplayHandler method
+    pub fn on_loading_progress_change(&mut self, browser: Option<Browser>, progress: f64) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        if let Some(browser) = browser {
+            self.update_browser_state(browser.identifier(), |state| {
+                state.loading_progress = progress
+            });
+        }
+    }
+
+    // CefLoadHandler method
+    pub fn on_loading_state_change(
+        &mut self,
+        browser: Option<Browser>,
+        is_loading: bool,
+        can_go_back: bool,
+        can_go_forward: bool,
+    ) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        if let Some(browser) = browser {
+            self.update_browser_state(browser.identifier(), |state| {
+                state.is_loading = is_loading;
+                state.can_go_back = can_go_back;
+                state.can_go_forward = can_go_forward;
+            });
+        }
+    }
+
+    // CefLoadHandler method
+    pub fn on_load_error(
+        &mut self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        failed_url: Option<&CefString>,
+    ) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        let (Some(browser), Some(frame)) = (browser, frame) else {
+            return;
+        };
+        if !self.certificate_policy.interstitial || frame.is_main() == 0 {
+            return;
+        }
+
+        // Show the certificate error page if the page failed because its
+        // certificate was denied.
+        let failed_url = failed_url.map(CefString::to_string).unwrap_or_default();
+        if self
+            .certificate_log
+            .last_denial(browser.identifier())
+            .is_some_and(|decision| decision.url == failed_url)
+        {
+            let url = format!("{TEST_ORIGIN}{CERTIFICATE_ERROR_PAGE_PATH}");
+            frame.load_url(Some(&CefString::from(url.as_str())));
+        }
+    }
+
     // CefClient method
     pub fn on_process_message_received(
         &self,
@@ -183,8 +849,14 @@ impl ClientManager {
         false
     }
 
+    /// |inspected_browser_id| is set if |browser| is the devtools window of that
+    /// browser.
     // CefLifeSpanHandler method
-    pub fn on_after_created(&mut self, browser: Option<Browser>) {
+    pub fn on_after_created(
+        &mut self,
+        browser: Option<Browser>,
+        inspected_browser_id: Option<i32>,
+    ) {
         debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
         if self.message_router.is_none() {
@@ -203,23 +875,70 @@ impl ClientManager {
                         .add_handler(Arc::new(message_handler), false)
                         .expect("Failed to add message handler"),
                 );
+                self.download_handler_id = Some(
+                    message_router
+                        .add_handler(
+                            Arc::new(DownloadMessageHandler::new(self.downloads.clone())),
+                            false,
+                        )
+                        .expect("Failed to add download handler"),
+                );
+                self.permission_handler_id = Some(
+                    message_router
+                        .add_handler(
+                            Arc::new(PermissionMessageHandler::new(self.permissions.clone())),
+                            false,
+                        )
+                        .expect("Failed to add permission handler"),
+                );
             }
         }
 
         self.browser_ct += 1;
         if let Some(browser) = browser {
-            self.browser_list.push(browser);
+            let role = match inspected_browser_id {
+                Some(inspected_browser_id) => BrowserRole::DevTools {
+                    inspected_browser_id,
+                },
+                None => {
+                    OPEN_BROWSER_COUNT.fetch_add(1, Ordering::SeqCst);
+                    BrowserRole::App
+                }
+            };
+            self.update_browser_state(browser.identifier(), |state| {
+                state.profile = get_profile_name(&browser);
+            });
+            self.browser_list.push(BrowserEntry { browser, role });
         }
+
+        // The manager of the newest window is the one the user interacts with.
+        self.set_active();
     }
 
     // CefLifeSpanHandler method
-    pub fn do_close(&mut self, _browser: Option<Browser>) -> i32 {
+    pub fn do_close(&mut self, browser: Option<Browser>) -> i32 {
         debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
+        let role = browser
+            .as_ref()
+            .and_then(|browser| self.find_browser(browser.identifier()))
+            .map(|entry| entry.role);
+        if role == Some(BrowserRole::App)
+            && let Some(browser) = browser.as_ref()
+        {
+            // DevTools don't outlive the browser they inspect.
+            self.close_devtools(browser.identifier());
+        }
+
         // Closing the main window requires special handling. See the DoClose()
         // documentation in the CEF header for a detailed destription of this
         // process.
-        if self.browser_list.len() == 1 {
+        let app_browser_ct = self
+            .browser_list
+            .iter()
+            .filter(|entry| entry.role == BrowserRole::App)
+            .count();
+        if role == Some(BrowserRole::App) && app_browser_ct == 1 {
             // The last browser window is closing.
             self.is_closing = true;
         }
@@ -233,83 +952,609 @@ impl ClientManager {
     pub fn on_before_close(&mut self, mut browser: Option<Browser>) {
         debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
+        if let Some(browser) = browser.as_ref() {
+            self.crash_tracker.remove(browser.identifier());
+            self.redirect_tracker.remove(browser.identifier());
+            self.downloads
+                .lock()
+                .expect("Failed to lock downloads")
+                .remove_browser(browser.identifier());
+            self.permissions
+                .lock()
+                .expect("Failed to lock permissions")
+                .remove_browser(browser.identifier());
+            notify_browser_state(self.browser_states.remove(browser.identifier()));
+        }
+
         self.browser_ct -= 1;
         if self.browser_ct == 0 {
             // Free the router when the last browser is closed.
-            if let Some(message_router) = self.message_router.take()
-                && let Some(message_handler_id) = self.message_handler_id.take()
-            {
-                message_router.remove_handler(message_handler_id);
+            if let Some(message_router) = self.message_router.take() {
+                if let Some(message_handler_id) = self.message_handler_id.take() {
+                    message_router.remove_handler(message_handler_id);
+                }
+                if let Some(download_handler_id) = self.download_handler_id.take() {
+                    message_router.remove_handler(download_handler_id);
+                }
+                if let Some(permission_handler_id) = self.permission_handler_id.take() {
+                    message_router.remove_handler(permission_handler_id);
+                }
             }
         }
 
         // Remove from the list of existing browsers.
-        for (i, browser_item) in self.browser_list.iter().enumerate() {
-            if browser_item.is_same(browser.as_mut()) != 0 {
+        for (i, entry) in self.browser_list.iter().enumerate() {
+            if entry.browser.is_same(browser.as_mut()) != 0 {
+                if entry.role == BrowserRole::App {
+                    OPEN_BROWSER_COUNT.fetch_sub(1, Ordering::SeqCst);
+                }
                 self.browser_list.remove(i);
                 break;
             }
         }
 
-        if self.browser_list.is_empty() {
-            // All browser windows have closed. Quit the application message loop.
+        if OPEN_BROWSER_COUNT.load(Ordering::SeqCst) == 0 {
+            // All browser windows of every manager have closed. Quit the application
+            // message loop.
             quit_message_loop();
         }
     }
 
-    // CefRequestHandler method
-    pub fn on_before_browse(&self, browser: Option<Browser>, frame: Option<Frame>) -> bool {
+    // CefDownloadHandler method
+    pub fn on_before_download(
+        &mut self,
+        browser: Option<Browser>,
+        download_item: Option<DownloadItem>,
+        suggested_name: Option<&CefString>,
+        callback: Option<BeforeDownloadCallback>,
+    ) -> bool {
         debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
-        if let Some(message_router) = self.message_router.as_ref() {
-            message_router.on_before_browse(browser, frame);
-        }
-
-        false
+        let (Some(browser), Some(download_item), Some(callback)) =
+            (browser, download_item, callback)
+        else {
+            return false;
+        };
+        let suggested_name = suggested_name.map(CefString::to_string).unwrap_or_default();
+        self.downloads
+            .lock()
+            .expect("Failed to lock downloads")
+            .on_before_download(
+                browser.identifier(),
+                &get_profile_name(&browser),
+                &download_item,
+                &suggested_name,
+                callback,
+            );
+        true
     }
 
-    // CefRequestHandler method
-    pub fn on_render_process_terminated(&self, browser: Option<Browser>) {
+    // CefDownloadHandler method
+    pub fn on_download_updated(
+        &mut self,
+        download_item: Option<DownloadItem>,
+        callback: Option<DownloadItemCallback>,
+    ) {
         debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
-        if let Some(message_router) = self.message_router.as_ref() {
-            message_router.on_render_process_terminated(browser);
+        if let Some(download_item) = download_item {
+            self.downloads
+                .lock()
+                .expect("Failed to lock downloads")
+                .on_download_updated(&download_item, callback);
         }
     }
 
-    // CefResourceRequestHandler method
-    pub fn resource_handler(&self, request: Option<Request>) -> Option<ResourceHandler> {
-        debug_assert_ne!(currently_on(ThreadId::IO), 0);
-        let url = CefString::from(&request?.url()).to_string();
-
-        // This is a minimal implementation of resource loading. For more complex
-        // usage (multiple files, zip archives, custom handlers, etc.) you might want
-        // to use CefResourceManager. See the "resource_manager" target for an
-        // example implementation.
-        if let Some(resource_path) = get_resource_path(&url) {
-            return get_resource_handler(&resource_path);
-        }
+    // CefPermissionHandler method
+    pub fn on_request_media_access_permission(
+        &mut self,
+        browser: Option<Browser>,
+        requesting_origin: Option<&CefString>,
+        requested_permissions: u32,
+        callback: Option<MediaAccessCallback>,
+    ) -> bool {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
 
-        None
+        let (Some(browser), Some(callback)) = (browser, callback) else {
+            return false;
+        };
+        let origin = requesting_origin
+            .map(CefString::to_string)
+            .unwrap_or_default();
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .on_request_media_access_permission(
+                browser.identifier(),
+                &get_profile_name(&browser),
+                &origin,
+                requested_permissions,
+                callback,
+            );
+        true
+    }
+
+    // CefPermissionHandler method
+    pub fn on_show_permission_prompt(
+        &mut self,
+        browser: Option<Browser>,
+        prompt_id: u64,
+        requesting_origin: Option<&CefString>,
+        requested_permissions: u32,
+        callback: Option<PermissionPromptCallback>,
+    ) -> bool {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        let (Some(browser), Some(callback)) = (browser, callback) else {
+            return false;
+        };
+        let origin = requesting_origin
+            .map(CefString::to_string)
+            .unwrap_or_default();
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .on_show_permission_prompt(
+                browser.identifier(),
+                &get_profile_name(&browser),
+                prompt_id,
+                &origin,
+                requested_permissions,
+                callback,
+            );
+        true
+    }
+
+    // CefPermissionHandler method
+    pub fn on_dismiss_permission_prompt(&mut self, prompt_id: u64) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .on_dismiss_permission_prompt(prompt_id);
+    }
+
+    // CefRequestHandler method
+    pub fn on_before_browse(
+        &mut self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        request: Option<Request>,
+        user_gesture: bool,
+        is_redirect: bool,
+    ) -> bool {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        // DevTools browsers navigate to their own origins.
+        let is_app_browser = browser.as_ref().is_some_and(|browser| {
+            self.find_browser(browser.identifier())
+                .is_none_or(|entry| entry.role == BrowserRole::App)
+        });
+        let decision = match (browser.as_ref(), frame.as_ref(), request.as_ref()) {
+            (Some(browser), Some(frame), Some(request)) if is_app_browser => {
+                let info = NavigationInfo {
+                    browser_id: browser.identifier(),
+                    url: CefString::from(&request.url()).to_string(),
+                    is_main_frame: frame.is_main() != 0,
+                    user_gesture,
+                    is_redirect,
+                };
+                let decision = self
+                    .navigation_policy
+                    .decide(&info, &mut self.redirect_tracker);
+                if decision != NavigationDecision::Allow && self.navigation_policy.log_decisions {
+                    eprintln!(
+                        "navigation of browser {} to {}: {decision:?}",
+                        info.browser_id, info.url
+                    );
+                }
+                if decision == NavigationDecision::OpenExternal
+                    && !(self.external_opener)(&info.url)
+                {
+                    eprintln!("failed to open {} externally", info.url);
+                }
+                decision
+            }
+            _ => NavigationDecision::Allow,
+        };
+        if decision != NavigationDecision::Allow {
+            return true;
+        }
+
+        if let Some(message_router) = self.message_router.as_ref() {
+            message_router.on_before_browse(browser, frame);
+        }
+
+        false
+    }
+
+    // CefRequestHandler method
+    pub fn on_certificate_error(
+        &mut self,
+        browser: Option<Browser>,
+        cert_error: Errorcode,
+        request_url: Option<&CefString>,
+        ssl_info: Option<Sslinfo>,
+        callback: Option<Callback>,
+    ) -> bool {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        let url = request_url.map(CefString::to_string).unwrap_or_default();
+        let host = get_host(&url).unwrap_or_default();
+        let fingerprints = ssl_info
+            .as_ref()
+            .map(get_certificate_fingerprints)
+            .unwrap_or_default();
+        let (allowed, reason) = self
+            .certificate_policy
+            .decide(&host, cert_error, &fingerprints);
+
+        let decision = CertificateDecision {
+            time: std::time::SystemTime::now(),
+            browser_id: browser.as_ref().map_or(0, |browser| browser.identifier()),
+            url,
+            host,
+            error: cert_error,
+            fingerprints,
+            allowed,
+            reason,
+        };
+        if self.certificate_policy.log_decisions {
+            eprintln!(
+                "certificate error {:?} for {}: {} ({})",
+                decision.error,
+                decision.url,
+                if decision.allowed {
+                    "allowed"
+                } else {
+                    "denied"
+                },
+                decision.reason,
+            );
+        }
+        self.certificate_log.record(decision);
+
+        match callback {
+            Some(callback) if allowed => {
+                callback.cont();
+                true
+            }
+            // Cancel the request.
+            _ => false,
+        }
+    }
+
+    // CefRequestHandler method
+    pub fn on_render_process_terminated(
+        &mut self,
+        browser: Option<Browser>,
+        status: TerminationStatus,
+        error_code: i32,
+        error_string: Option<&CefString>,
+    ) {
+        debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+        if let Some(message_router) = self.message_router.as_ref() {
+            message_router.on_render_process_terminated(browser.clone());
+        }
+
+        let Some(browser) = browser else {
+            return;
+        };
+        let Some(frame) = browser.main_frame() else {
+            return;
+        };
+
+        let browser_id = browser.identifier();
+        let crash_page_url = format!("{TEST_ORIGIN}{CRASH_PAGE_PATH}");
+        let mut url = CefString::from(&frame.url()).to_string();
+        if url.starts_with(&crash_page_url) {
+            // The crash page itself crashed, keep the page that crashed originally.
+            if let Some(last_crash) = self.crash_tracker.last_crash(browser_id) {
+                url = last_crash.url.clone();
+            }
+        }
+
+        let crash_count = self.crash_tracker.record(browser_id, &self.crash_policy);
+        let info = CrashInfo {
+            browser_id,
+            status,
+            error_code,
+            error_string: error_string.map(CefString::to_string).unwrap_or_default(),
+            url,
+            crash_count,
+        };
+        eprintln!(
+            "render process terminated: browser={} status={:?} error_code={} error={:?} url={} crashes={}",
+            info.browser_id,
+            info.status,
+            info.error_code,
+            info.error_string,
+            info.url,
+            info.crash_count,
+        );
+
+        if let Some(callback) = self.crash_callback.clone() {
+            let mut task = NotifyCrash::new(callback, info.clone());
+            post_task(ThreadId::UI, Some(&mut task));
+        }
+
+        let action = self.crash_tracker.action(crash_count, &self.crash_policy);
+        let url = info.url.clone();
+        self.crash_tracker.set_last_crash(info);
+
+        if self.crash_policy.show_crash_page {
+            frame.load_url(Some(&CefString::from(crash_page_url.as_str())));
+        }
+
+        match action {
+            CrashAction::Reload { delay } => {
+                let mut task = ReloadCrashedBrowser::new(browser, url);
+                post_delayed_task(
+                    ThreadId::UI,
+                    Some(&mut task),
+                    delay.as_millis().try_into().unwrap_or(i64::MAX),
+                );
+            }
+            CrashAction::GiveUp => {
+                eprintln!("giving up reloading browser {browser_id} after {crash_count} crashes");
+            }
+            CrashAction::Ignore => {}
+        }
+    }
+
+    fn is_crash_page(request: Option<&Request>) -> bool {
+        request.is_some_and(|request| {
+            let url = CefString::from(&request.url()).to_string();
+            get_resource_path(&url).as_deref() == Some(CRASH_PAGE_PATH)
+        })
+    }
+
+    fn is_certificate_error_page(request: Option<&Request>) -> bool {
+        request.is_some_and(|request| {
+            let url = CefString::from(&request.url()).to_string();
+            get_resource_path(&url).as_deref() == Some(CERTIFICATE_ERROR_PAGE_PATH)
+        })
+    }
+
+    // CefResourceRequestHandler method
+    pub fn on_before_resource_load(
+        &self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        request: Option<Request>,
+        callback: Option<Callback>,
+    ) -> ReturnValue {
+        debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+        if self.is_served_directly(request.as_ref()) {
+            // Served by `ClientManager::resource_handler` without the pipeline.
+            return ReturnValue::CONTINUE;
+        }
+
+        let Some(request) = request else {
+            return self
+                .resource_pipeline
+                .on_before_resource_load(browser, frame, None, callback);
+        };
+        let resource_pipeline = self.resource_pipeline.clone();
+        let next: ContinueRequest = Arc::new(move |request, callback| {
+            resource_pipeline.on_before_resource_load(
+                browser.clone(),
+                frame.clone(),
+                Some(request),
+                callback,
+            )
+        });
+        self.request_rules.apply(request, callback, next)
+    }
+
+    // CefResourceRequestHandler method
+    pub fn resource_handler(
+        &self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        request: Option<Request>,
+    ) -> Option<ResourceHandler> {
+        let lookup_browser = browser.clone();
+        let lookup_request = request.clone();
+        self.serve_request(browser, request, move || {
+            self.resource_pipeline
+                .resource_handler(lookup_browser, frame, lookup_request)
+        })
+    }
+
+    /// Returns the handler for |request| of a registered scheme that didn't pass
+    /// through `resource_handler`, e.g. because it has no browser. It gets the same
+    /// built-in pages, checks and headers, with the mounts looked up directly.
+    pub fn scheme_resource_handler(
+        &self,
+        browser: Option<Browser>,
+        request: Option<Request>,
+    ) -> Option<ResourceHandler> {
+        let url = CefString::from(&request.as_ref()?.url()).to_string();
+        self.serve_request(browser, request, || self.mount_table.resource_handler(&url))
+    }
+
+    /// Serves the built-in pages and CSP reports, and answers requests to mounted
+    /// origins with the handler of |lookup| or an error response.
+    fn serve_request(
+        &self,
+        browser: Option<Browser>,
+        request: Option<Request>,
+        lookup: impl FnOnce() -> Option<ResourceHandler>,
+    ) -> Option<ResourceHandler> {
+        debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+        if Self::is_crash_page(request.as_ref()) {
+            let info = browser
+                .as_ref()
+                .and_then(|browser| self.crash_tracker.last_crash(browser.identifier()));
+            let will_reload = info.is_some_and(|info| {
+                matches!(
+                    self.crash_tracker
+                        .action(info.crash_count, &self.crash_policy),
+                    CrashAction::Reload { .. }
+                )
+            });
+            let html = crash_page_html(info, will_reload);
+            return get_data_resource_handler("text/html", html.into_bytes());
+        }
+
+        if Self::is_certificate_error_page(request.as_ref()) {
+            let decision = browser
+                .as_ref()
+                .and_then(|browser| self.certificate_log.last_denial(browser.identifier()));
+            let html = certificate_error_page_html(decision);
+            return get_data_resource_handler("text/html", html.into_bytes());
+        }
+
+        let url = CefString::from(&request.as_ref()?.url()).to_string();
+        if self.is_csp_report(&url) {
+            return self.csp_report_handler(&url, request.as_ref()?);
+        }
+
+        let handler = lookup();
+
+        // Requests to mounted origins never fall through to the network.
+        let Some((mount, resource_path)) = self.mount_table.resolve(&url) else {
+            return handler;
+        };
+        if matches!(mount.source, MountSource::Proxy(_)) {
+            // The dev server answers with its own status codes and headers.
+            return handler;
+        }
+        // Decoded once from the URL path, the same way `Mount::resource_handler` does.
+        let response = match normalize_resource_path(&resource_path) {
+            // Hidden files and paths outside of the mount are never served.
+            None => LocalResponse::Forbidden,
+            Some(path) if path.split('/').any(|segment| segment.starts_with('.')) => {
+                LocalResponse::Forbidden
+            }
+            Some(_) => match handler {
+                Some(handler) => LocalResponse::Resource(handler),
+                None => LocalResponse::NotFound,
+            },
+        };
+        Some(create_local_resource_handler(
+            response,
+            mount.policy.clone(),
+            &mount.prefix,
+        ))
+    }
+
+    // CefResourceRequestHandler method
+    pub fn resource_response_filter(
+        &self,
+        request: Option<Request>,
+        response: Option<Response>,
+    ) -> Option<ResponseFilter> {
+        debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+        let url = CefString::from(&request?.url()).to_string();
+        let mime_type = CefString::from(&response?.mime_type()).to_string();
+        self.response_filters.response_filter(&url, &mime_type)
+    }
+
+    /// Handles violation reports sent to the `CSP_REPORT_PATH` of a mount with a
+    /// report-only policy.
+    fn is_csp_report(&self, url: &str) -> bool {
+        self.mount_table
+            .resolve(url)
+            .is_some_and(|(mount, resource_path)| {
+                resource_path == CSP_REPORT_PATH
+                    && mount
+                        .policy
+                        .security
+                        .as_ref()
+                        .is_some_and(|security| security.accepts_reports())
+            })
+    }
+
+    /// Returns true for requests that `ClientManager::resource_handler` answers
+    /// without asking the resource pipeline.
+    fn is_served_directly(&self, request: Option<&Request>) -> bool {
+        Self::is_crash_page(request)
+            || Self::is_certificate_error_page(request)
+            || request.is_some_and(|request| {
+                self.is_csp_report(&CefString::from(&request.url()).to_string())
+            })
+    }
+
+    fn csp_report_handler(&self, url: &str, request: &Request) -> Option<ResourceHandler> {
+        let (mount, _) = self.mount_table.resolve(url)?;
+
+        let method = CefString::from(&request.method()).to_string();
+        let response = if method.eq_ignore_ascii_case("POST") {
+            let report = CspReport {
+                prefix: mount.prefix.clone(),
+                body: String::from_utf8_lossy(&get_post_data_bytes(request)).into_owned(),
+            };
+            match self.csp_report_callback.clone() {
+                Some(callback) => {
+                    // The callback may use this manager, so call it once the lock is
+                    // released.
+                    let mut task = NotifyCspReport::new(callback, report);
+                    post_task(ThreadId::UI, Some(&mut task));
+                }
+                None => eprintln!("CSP violation on {}: {}", report.prefix, report.body),
+            }
+            LocalResponse::NoContent
+        } else {
+            LocalResponse::NotFound
+        };
+        Some(create_local_resource_handler(
+            response,
+            mount.policy.clone(),
+            &mount.prefix,
+        ))
     }
 }
 
-pub fn setup_client(startup_url: String) -> Client {
-    let manager = ClientManager::new(startup_url);
+/// Creates a `ClientManager` and the client for its browsers. The manager is
+/// returned as well so that it can be configured before the first browser exists.
+pub fn setup_client(
+    startup_url: String,
+    mount_table: MountTable,
+) -> (Client, Arc<Mutex<ClientManager>>) {
+    let manager = ClientManager::new(startup_url, mount_table);
+    (create_client(manager.clone(), None), manager)
+}
 
+/// Returns a client for the browsers of |manager|. |inspected_browser_id| is set
+/// for the client of a devtools window.
+fn create_client(manager: Arc<Mutex<ClientManager>>, inspected_browser_id: Option<i32>) -> Client {
     let display_handler = DisplayHandlerImpl::new(manager.clone());
-    let life_span_handler = LifeSpanHandlerImpl::new(manager.clone());
-    let resource_request_handler = ResourceRequestHandlerImpl::new(manager.clone());
+    let download_handler = DownloadHandlerImpl::new(manager.clone());
+    let keyboard_handler = KeyboardHandlerImpl::new(manager.clone());
+    let life_span_handler = LifeSpanHandlerImpl::new(manager.clone(), inspected_browser_id);
+    let load_handler = LoadHandlerImpl::new(manager.clone());
+    let permission_handler = PermissionHandlerImpl::new(manager.clone());
+    let resource_request_handler = ResourceRequestHandlerImpl::new(manager.clone(), None);
     let request_handler = RequestHandlerImpl::new(manager.clone(), resource_request_handler);
 
-    ClientImpl::new(manager, display_handler, life_span_handler, request_handler)
+    ClientImpl::new(
+        manager,
+        display_handler,
+        download_handler,
+        keyboard_handler,
+        life_span_handler,
+        load_handler,
+        permission_handler,
+        request_handler,
+    )
 }
 
 wrap_client! {
     struct ClientImpl {
         manager: Arc<Mutex<ClientManager>>,
         display_handler: DisplayHandler,
+        download_handler: DownloadHandler,
+        keyboard_handler: KeyboardHandler,
         life_span_handler: LifeSpanHandler,
+        load_handler: LoadHandler,
+        permission_handler: PermissionHandler,
         request_handler: RequestHandler,
     }
 
@@ -334,10 +1579,26 @@ wrap_client! {
             Some(self.display_handler.clone())
         }
 
+        fn download_handler(&self) -> Option<DownloadHandler> {
+            Some(self.download_handler.clone())
+        }
+
+        fn keyboard_handler(&self) -> Option<KeyboardHandler> {
+            Some(self.keyboard_handler.clone())
+        }
+
         fn life_span_handler(&self) -> Option<LifeSpanHandler> {
             Some(self.life_span_handler.clone())
         }
 
+        fn load_handler(&self) -> Option<LoadHandler> {
+            Some(self.load_handler.clone())
+        }
+
+        fn permission_handler(&self) -> Option<PermissionHandler> {
+            Some(self.permission_handler.clone())
+        }
+
         fn request_handler(&self) -> Option<RequestHandler> {
             Some(self.request_handler.clone())
         }
@@ -351,21 +1612,154 @@ wrap_display_handler! {
 
     impl DisplayHandler {
         fn on_title_change(&self, browser: Option<&mut Browser>, title: Option<&CefString>) {
-            let inner = self.inner.lock().expect("Failed to lock inner");
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
             inner.on_title_change(browser.cloned(), title);
         }
+
+        fn on_address_change(
+            &self,
+            browser: Option<&mut Browser>,
+            frame: Option<&mut Frame>,
+            url: Option<&CefString>,
+        ) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_address_change(browser.cloned(), frame.cloned(), url);
+        }
+
+        fn on_favicon_urlchange(
+            &self,
+            browser: Option<&mut Browser>,
+            icon_urls: Option<&mut CefStringList>,
+        ) {
+            let favicon_urls = icon_urls
+                .map(|icon_urls| icon_urls.clone().into_iter().collect())
+                .unwrap_or_default();
+
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_favicon_urlchange(browser.cloned(), favicon_urls);
+        }
+
+        fn on_fullscreen_mode_change(
+            &self,
+            browser: Option<&mut Browser>,
+            fullscreen: ::std::os::raw::c_int,
+        ) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_fullscreen_mode_change(browser.cloned(), fullscreen != 0);
+        }
+
+        fn on_loading_progress_change(&self, browser: Option<&mut Browser>, progress: f64) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_loading_progress_change(browser.cloned(), progress);
+        }
+    }
+}
+
+wrap_download_handler! {
+    struct DownloadHandlerImpl {
+        inner: Arc<Mutex<ClientManager>>,
+    }
+
+    impl DownloadHandler {
+        fn on_before_download(
+            &self,
+            browser: Option<&mut Browser>,
+            download_item: Option<&mut DownloadItem>,
+            suggested_name: Option<&CefString>,
+            callback: Option<&mut BeforeDownloadCallback>,
+        ) -> ::std::os::raw::c_int {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner
+                .on_before_download(
+                    browser.cloned(),
+                    download_item.cloned(),
+                    suggested_name,
+                    callback.cloned(),
+                )
+                .into()
+        }
+
+        fn on_download_updated(
+            &self,
+            _browser: Option<&mut Browser>,
+            download_item: Option<&mut DownloadItem>,
+            callback: Option<&mut DownloadItemCallback>,
+        ) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_download_updated(download_item.cloned(), callback.cloned());
+        }
+    }
+}
+
+wrap_load_handler! {
+    struct LoadHandlerImpl {
+        inner: Arc<Mutex<ClientManager>>,
+    }
+
+    impl LoadHandler {
+        fn on_loading_state_change(
+            &self,
+            browser: Option<&mut Browser>,
+            is_loading: ::std::os::raw::c_int,
+            can_go_back: ::std::os::raw::c_int,
+            can_go_forward: ::std::os::raw::c_int,
+        ) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_loading_state_change(
+                browser.cloned(),
+                is_loading != 0,
+                can_go_back != 0,
+                can_go_forward != 0,
+            );
+        }
+
+        fn on_load_error(
+            &self,
+            browser: Option<&mut Browser>,
+            frame: Option<&mut Frame>,
+            _error_code: Errorcode,
+            _error_text: Option<&CefString>,
+            failed_url: Option<&CefString>,
+        ) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_load_error(browser.cloned(), frame.cloned(), failed_url);
+        }
+    }
+}
+
+wrap_keyboard_handler! {
+    struct KeyboardHandlerImpl {
+        inner: Arc<Mutex<ClientManager>>,
+    }
+
+    impl KeyboardHandler {
+        fn on_pre_key_event(
+            &self,
+            browser: Option<&mut Browser>,
+            event: Option<&KeyEvent>,
+            _os_event: sys::cef_event_handle_t,
+            _is_keyboard_shortcut: Option<&mut ::std::os::raw::c_int>,
+        ) -> ::std::os::raw::c_int {
+            let Some(event) = event else {
+                return 0;
+            };
+
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_pre_key_event(browser.cloned(), event).into()
+        }
     }
 }
 
 wrap_life_span_handler! {
     struct LifeSpanHandlerImpl {
         inner: Arc<Mutex<ClientManager>>,
+        inspected_browser_id: Option<i32>,
     }
 
     impl LifeSpanHandler {
         fn on_after_created(&self, browser: Option<&mut Browser>) {
             let mut inner = self.inner.lock().expect("Failed to lock inner");
-            inner.on_after_created(browser.cloned());
+            inner.on_after_created(browser.cloned(), self.inspected_browser_id);
         }
 
         fn do_close(&self, browser: Option<&mut Browser>) -> i32 {
@@ -380,6 +1774,63 @@ wrap_life_span_handler! {
     }
 }
 
+wrap_permission_handler! {
+    struct PermissionHandlerImpl {
+        inner: Arc<Mutex<ClientManager>>,
+    }
+
+    impl PermissionHandler {
+        fn on_request_media_access_permission(
+            &self,
+            browser: Option<&mut Browser>,
+            _frame: Option<&mut Frame>,
+            requesting_origin: Option<&CefString>,
+            requested_permissions: u32,
+            callback: Option<&mut MediaAccessCallback>,
+        ) -> ::std::os::raw::c_int {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner
+                .on_request_media_access_permission(
+                    browser.cloned(),
+                    requesting_origin,
+                    requested_permissions,
+                    callback.cloned(),
+                )
+                .into()
+        }
+
+        fn on_show_permission_prompt(
+            &self,
+            browser: Option<&mut Browser>,
+            prompt_id: u64,
+            requesting_origin: Option<&CefString>,
+            requested_permissions: u32,
+            callback: Option<&mut PermissionPromptCallback>,
+        ) -> ::std::os::raw::c_int {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner
+                .on_show_permission_prompt(
+                    browser.cloned(),
+                    prompt_id,
+                    requesting_origin,
+                    requested_permissions,
+                    callback.cloned(),
+                )
+                .into()
+        }
+
+        fn on_dismiss_permission_prompt(
+            &self,
+            _browser: Option<&mut Browser>,
+            prompt_id: u64,
+            _result: PermissionRequestResult,
+        ) {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_dismiss_permission_prompt(prompt_id);
+        }
+    }
+}
+
 wrap_request_handler! {
     struct RequestHandlerImpl {
         inner: Arc<Mutex<ClientManager>>,
@@ -395,8 +1846,16 @@ wrap_request_handler! {
             user_gesture: i32,
             is_redirect: i32
         ) -> i32 {
-            let inner = self.inner.lock().expect("Failed to lock inner");
-            inner.on_before_browse(browser.cloned(), frame.cloned()).into()
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner
+                .on_before_browse(
+                    browser.cloned(),
+                    frame.cloned(),
+                    request.cloned(),
+                    user_gesture != 0,
+                    is_redirect != 0,
+                )
+                .into()
         }
 
         fn resource_request_handler(
@@ -410,7 +1869,36 @@ wrap_request_handler! {
             disable_default_handling: Option<&mut ::std::os::raw::c_int>,
         ) -> Option<ResourceRequestHandler> {
             debug_assert_ne!(currently_on(ThreadId::IO), 0);
-            Some(self.resource_request_handler.clone())
+
+            // Content routes are looked up once per request.
+            let inner = self.inner.lock().expect("Failed to lock inner");
+            match inner.content_handler(request.as_deref()) {
+                Some(handler) => Some(ResourceRequestHandlerImpl::new(
+                    self.inner.clone(),
+                    Some(handler),
+                )),
+                None => Some(self.resource_request_handler.clone()),
+            }
+        }
+
+        fn on_certificate_error(
+            &self,
+            browser: Option<&mut Browser>,
+            cert_error: Errorcode,
+            request_url: Option<&CefString>,
+            ssl_info: Option<&mut Sslinfo>,
+            callback: Option<&mut Callback>,
+        ) -> ::std::os::raw::c_int {
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner
+                .on_certificate_error(
+                    browser.cloned(),
+                    cert_error,
+                    request_url,
+                    ssl_info.cloned(),
+                    callback.cloned(),
+                )
+                .into()
         }
 
         fn on_render_process_terminated(
@@ -420,8 +1908,8 @@ wrap_request_handler! {
             error_code: i32,
             error_string: Option<&CefString>
         ) {
-            let inner = self.inner.lock().expect("Failed to lock inner");
-            inner.on_render_process_terminated(browser.cloned());
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_render_process_terminated(browser.cloned(), status, error_code, error_string);
         }
     }
 }
@@ -429,6 +1917,8 @@ wrap_request_handler! {
 wrap_resource_request_handler! {
     struct ResourceRequestHandlerImpl {
         inner: Arc<Mutex<ClientManager>>,
+        // Set for requests answered by a content route.
+        content_handler: Option<ContentHandler>,
     }
 
     impl ResourceRequestHandler {
@@ -439,17 +1929,46 @@ wrap_resource_request_handler! {
             request: Option<&mut Request>,
             callback: Option<&mut Callback>,
         ) -> ReturnValue {
-            ReturnValue::CONTINUE
+            if self.content_handler.is_some() {
+                return ReturnValue::CONTINUE;
+            }
+
+            let inner = self.inner.lock().expect("Failed to lock inner");
+            inner.on_before_resource_load(
+                browser.cloned(),
+                frame.cloned(),
+                request.cloned(),
+                callback.cloned(),
+            )
         }
 
         fn resource_handler(
+            &self,
+            browser: Option<&mut Browser>,
+            frame: Option<&mut Frame>,
+            request: Option<&mut Request>,
+        ) -> Option<ResourceHandler> {
+            if let Some(handler) = self.content_handler.as_ref() {
+                let profile = browser
+                    .as_deref()
+                    .map(get_profile_name)
+                    .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+                return Some(create_content_resource_handler(handler.clone(), profile));
+            }
+
+            let inner = self.inner.lock().expect("Failed to lock inner");
+            inner.resource_handler(browser.cloned(), frame.cloned(), request.cloned())
+        }
+
+        fn resource_response_filter(
             &self,
             _browser: Option<&mut Browser>,
             _frame: Option<&mut Frame>,
             request: Option<&mut Request>,
-        ) -> Option<ResourceHandler> {
+            response: Option<&mut Response>,
+        ) -> Option<ResponseFilter> {
             let inner = self.inner.lock().expect("Failed to lock inner");
-            inner.resource_handler(request.cloned())
+            inner.resource_response_filter(request.cloned(), response.cloned())
         }
     }
 }
@@ -484,3 +2003,148 @@ wrap_task! {
         }
     }
 }
+
+wrap_task! {
+    struct ReloadMounts {
+        inner: Arc<Mutex<ClientManager>>,
+        prefixes: Vec<String>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            let inner = self.inner.lock().expect("Failed to lock inner");
+            inner.reload_mounts(&self.prefixes);
+        }
+    }
+}
+
+wrap_task! {
+    struct NotifyCrash {
+        callback: CrashCallback,
+        info: CrashInfo,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            (self.callback)(&self.info);
+        }
+    }
+}
+
+/// Notifies the listeners of |change| once the `ClientManager` is unlocked.
+fn notify_browser_state(change: Option<BrowserStateChange>) {
+    if let Some(change) = change {
+        let mut task = NotifyBrowserState::new(Arc::new(change));
+        post_task(ThreadId::UI, Some(&mut task));
+    }
+}
+
+wrap_task! {
+    struct NotifyBrowserState {
+        change: Arc<BrowserStateChange>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            self.change.notify();
+        }
+    }
+}
+
+wrap_task! {
+    struct NotifyCspReport {
+        callback: CspReportCallback,
+        report: CspReport,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            (self.callback)(&self.report);
+        }
+    }
+}
+
+wrap_task! {
+    struct ReloadCrashedBrowser {
+        browser: Browser,
+        url: String,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            if let Some(frame) = self.browser.main_frame() {
+                frame.load_url(Some(&CefString::from(self.url.as_str())));
+            }
+        }
+    }
+}
+
+wrap_task! {
+    struct ShowDevTools {
+        inner: Arc<Mutex<ClientManager>>,
+        browser_id: i32,
+        inspect_at: Option<Point>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            let mut inner = self.inner.lock().expect("Failed to lock inner");
+            inner.show_devtools(self.browser_id, self.inspect_at);
+        }
+    }
+}
+
+wrap_task! {
+    struct OpenDevTools {
+        browser_host: BrowserHost,
+        client: Client,
+        inspect_at: Option<Point>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            let window_info = WindowInfo::default();
+            #[cfg(target_os = "windows")]
+            let window_info = window_info.set_as_popup(Default::default(), "DevTools");
+
+            let mut client = self.client.clone();
+            let settings = BrowserSettings::default();
+            self.browser_host.show_dev_tools(
+                Some(&window_info),
+                Some(&mut client),
+                Some(&settings),
+                self.inspect_at.as_ref(),
+            );
+        }
+    }
+}
+
+wrap_task! {
+    struct CloseDevTools {
+        browser: Browser,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+            if let Some(browser_host) = self.browser.host() {
+                browser_host.close_dev_tools();
+            }
+        }
+    }
+}
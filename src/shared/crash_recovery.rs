@@ -0,0 +1,225 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use cef::*;
+
+/// Details about a render process termination.
+#[derive(Debug, Clone)]
+pub struct CrashInfo {
+    pub browser_id: i32,
+    pub status: TerminationStatus,
+    pub error_code: i32,
+    pub error_string: String,
+    /// URL of the main frame at the time of the crash.
+    pub url: String,
+    /// Number of crashes of this browser within the policy window, including this one.
+    pub crash_count: usize,
+}
+
+pub type CrashCallback = Arc<dyn Fn(&CrashInfo) + Send + Sync>;
+
+/// Controls how a browser recovers after its render process terminates.
+#[derive(Debug, Clone)]
+pub struct CrashRecoveryPolicy {
+    /// Show the built-in crash page while waiting for the reload.
+    pub show_crash_page: bool,
+    /// Reload the crashed page automatically.
+    pub auto_reload: bool,
+    /// Delay before the first reload. Doubled for every further crash in the window.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Stop reloading once this many crashes happened within |crash_window|.
+    pub max_crashes: usize,
+    pub crash_window: Duration,
+}
+
+impl Default for CrashRecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            show_crash_page: true,
+            auto_reload: true,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            max_crashes: 3,
+            crash_window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashAction {
+    Reload { delay: Duration },
+    GiveUp,
+    Ignore,
+}
+
+/// Keeps the recent crash history of each browser.
+#[derive(Debug, Default)]
+pub struct CrashTracker {
+    history: HashMap<i32, VecDeque<Instant>>,
+    last_crash: HashMap<i32, CrashInfo>,
+}
+
+impl CrashTracker {
+    /// Records a crash and returns the number of crashes within the policy window.
+    pub fn record(&mut self, browser_id: i32, policy: &CrashRecoveryPolicy) -> usize {
+        self.record_at(browser_id, Instant::now(), policy)
+    }
+
+    fn record_at(&mut self, browser_id: i32, now: Instant, policy: &CrashRecoveryPolicy) -> usize {
+        let history = self.history.entry(browser_id).or_default();
+        while history
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > policy.crash_window)
+        {
+            history.pop_front();
+        }
+        history.push_back(now);
+        history.len()
+    }
+
+    pub fn action(&self, crash_count: usize, policy: &CrashRecoveryPolicy) -> CrashAction {
+        if !policy.auto_reload {
+            return CrashAction::Ignore;
+        }
+        if crash_count >= policy.max_crashes {
+            return CrashAction::GiveUp;
+        }
+
+        let exponent = crash_count.saturating_sub(1).min(16) as u32;
+        let delay = policy
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(policy.max_backoff);
+        CrashAction::Reload { delay }
+    }
+
+    pub fn set_last_crash(&mut self, info: CrashInfo) {
+        self.last_crash.insert(info.browser_id, info);
+    }
+
+    pub fn last_crash(&self, browser_id: i32) -> Option<&CrashInfo> {
+        self.last_crash.get(&browser_id)
+    }
+
+    pub fn remove(&mut self, browser_id: i32) {
+        self.history.remove(&browser_id);
+        self.last_crash.remove(&browser_id);
+    }
+}
+
+/// Minimal escaping for text inserted into the generated error pages.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the HTML of the built-in crash page.
+pub fn crash_page_html(info: Option<&CrashInfo>, will_reload: bool) -> String {
+    let details = match info {
+        Some(info) => format!(
+            "<p>Status: {:?}<br/>Error code: {}<br/>{}</p><p>URL: {}</p>",
+            info.status,
+            info.error_code,
+            escape_html(&info.error_string),
+            escape_html(&info.url),
+        ),
+        None => String::new(),
+    };
+    let reload = if will_reload {
+        "<p>The page will be reloaded automatically.</p>"
+    } else {
+        "<p>The page crashed too often and will not be reloaded.</p>"
+    };
+
+    format!(
+        "<html>\n<head>\n<title>Page Crashed</title>\n</head>\n\
+         <body bgcolor=\"white\">\n<h2>The page has crashed.</h2>\n{details}\n{reload}\n</body>\n</html>\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> CrashRecoveryPolicy {
+        CrashRecoveryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            max_crashes: 5,
+            crash_window: Duration::from_secs(60),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let tracker = CrashTracker::default();
+        let policy = policy();
+        let delay = |crash_count| match tracker.action(crash_count, &policy) {
+            CrashAction::Reload { delay } => Some(delay.as_secs()),
+            _ => None,
+        };
+        assert_eq!(delay(1), Some(1));
+        assert_eq!(delay(2), Some(2));
+        assert_eq!(delay(3), Some(4));
+        // Capped at |max_backoff|.
+        assert_eq!(delay(4), Some(5));
+        // Reaching |max_crashes| gives up.
+        assert_eq!(tracker.action(5, &policy), CrashAction::GiveUp);
+        assert_eq!(tracker.action(6, &policy), CrashAction::GiveUp);
+
+        let policy = CrashRecoveryPolicy {
+            auto_reload: false,
+            ..policy
+        };
+        assert_eq!(tracker.action(1, &policy), CrashAction::Ignore);
+    }
+
+    #[test]
+    fn default_policy_gives_up_at_max_crashes() {
+        let tracker = CrashTracker::default();
+        let policy = CrashRecoveryPolicy::default();
+        assert!(matches!(
+            tracker.action(policy.max_crashes - 1, &policy),
+            CrashAction::Reload { .. }
+        ));
+        assert_eq!(
+            tracker.action(policy.max_crashes, &policy),
+            CrashAction::GiveUp
+        );
+    }
+
+    #[test]
+    fn crash_window() {
+        let mut tracker = CrashTracker::default();
+        let policy = policy();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(tracker.record_at(1, at(0), &policy), 1);
+        assert_eq!(tracker.record_at(1, at(30), &policy), 2);
+        // Other browsers are counted separately.
+        assert_eq!(tracker.record_at(2, at(30), &policy), 1);
+        assert_eq!(tracker.record_at(1, at(60), &policy), 3);
+        // The first crash left the window.
+        assert_eq!(tracker.record_at(1, at(61), &policy), 3);
+        assert_eq!(tracker.record_at(1, at(200), &policy), 1);
+
+        tracker.remove(1);
+        assert_eq!(tracker.record_at(1, at(200), &policy), 1);
+    }
+}
@@ -0,0 +1,928 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cef::{
+    wrapper::message_router::{BrowserSideCallback, BrowserSideHandler},
+    *,
+};
+
+use crate::shared::{
+    navigation_policy::has_origin,
+    profile::{get_profile_name, get_profiles_dir},
+    resource_util::TEST_ORIGIN,
+    scheme::APP_ORIGIN,
+};
+
+/// Persistent query that receives the download events of the querying browser.
+pub const DOWNLOAD_EVENTS_MESSAGE: &str = "DownloadEvents";
+/// Query answered with the download history as a JSON array.
+pub const DOWNLOAD_HISTORY_MESSAGE: &str = "DownloadHistory";
+/// Commands followed by the download id, e.g. `DownloadPause:3`.
+const DOWNLOAD_PAUSE_PREFIX: &str = "DownloadPause:";
+const DOWNLOAD_RESUME_PREFIX: &str = "DownloadResume:";
+const DOWNLOAD_CANCEL_PREFIX: &str = "DownloadCancel:";
+
+/// Number of finished downloads kept in the history of each profile.
+const HISTORY_SIZE: usize = 1000;
+/// Name of the file the history of a profile is stored in.
+const HISTORY_FILE_NAME: &str = "download_history.tsv";
+
+/// Returns the `Downloads` directory of the user, or the temporary directory if
+/// there is no home directory.
+pub fn get_default_download_dir() -> PathBuf {
+    std::env::home_dir()
+        .map(|home| home.join("Downloads"))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Controls where downloads are saved.
+#[derive(Debug, Clone)]
+pub struct DownloadPolicy {
+    pub directory: PathBuf,
+    /// Let the user pick the path with the save dialog, starting at the path
+    /// chosen by the policy.
+    pub show_dialog: bool,
+    /// Lower case extensions of files that are never downloaded, e.g. `exe`.
+    pub blocked_extensions: Vec<String>,
+    /// Directory that finished, canceled and interrupted downloads are recorded
+    /// in, in `<profile>/download_history.tsv` per profile. No history is kept
+    /// if `None`.
+    pub history_dir: Option<PathBuf>,
+    /// Origins of the pages that may watch and control downloads.
+    pub ui_origins: Vec<String>,
+}
+
+impl Default for DownloadPolicy {
+    fn default() -> Self {
+        Self {
+            directory: get_default_download_dir(),
+            show_dialog: false,
+            blocked_extensions: Vec::new(),
+            history_dir: Some(get_profiles_dir()),
+            ui_origins: vec![TEST_ORIGIN.to_string(), APP_ORIGIN.to_string()],
+        }
+    }
+}
+
+/// Keeps |name| a plain file name that is valid on every platform.
+fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "<>:\"|?*".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+    if name.is_empty() {
+        "download".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+impl DownloadPolicy {
+    /// Returns the path to save the file suggested as |suggested_name| to, or
+    /// `None` if the download is blocked. Existing files and the paths in |taken|
+    /// are never overwritten; a counter is added to the name instead.
+    pub fn destination(&self, suggested_name: &str, taken: &[&Path]) -> Option<PathBuf> {
+        let name = sanitize_file_name(suggested_name);
+        let path = Path::new(&name);
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if self
+            .blocked_extensions
+            .iter()
+            .any(|blocked| blocked.eq_ignore_ascii_case(&extension))
+        {
+            return None;
+        }
+
+        let is_free = |path: &Path| !path.exists() && !taken.contains(&path);
+        let candidate = self.directory.join(&name);
+        if is_free(&candidate) {
+            return Some(candidate);
+        }
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let suffix = if extension.is_empty() {
+            String::new()
+        } else {
+            format!(".{extension}")
+        };
+        (1..)
+            .map(|n| self.directory.join(format!("{stem} ({n}){suffix}")))
+            .find(|candidate| is_free(candidate))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadState {
+    InProgress,
+    Paused,
+    Complete,
+    Canceled,
+    Interrupted,
+}
+
+impl DownloadState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::InProgress => "in_progress",
+            Self::Paused => "paused",
+            Self::Complete => "complete",
+            Self::Canceled => "canceled",
+            Self::Interrupted => "interrupted",
+        }
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        [
+            Self::InProgress,
+            Self::Paused,
+            Self::Complete,
+            Self::Canceled,
+            Self::Interrupted,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == state)
+    }
+
+    /// Returns true once CEF is done with the download.
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Complete | Self::Canceled | Self::Interrupted)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadRecord {
+    /// The CEF download id, unique within a session. 0 for entries of the history.
+    pub id: u32,
+    pub url: String,
+    pub path: PathBuf,
+    pub mime_type: String,
+    pub state: DownloadState,
+    pub received_bytes: i64,
+    /// -1 if the size is unknown.
+    pub total_bytes: i64,
+    /// Seconds since the Unix epoch when the download finished, 0 before.
+    pub end_time: u64,
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn escape_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape_field(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some(c) => result.push(c),
+            None => {}
+        }
+    }
+    result
+}
+
+impl DownloadRecord {
+    /// Returns the record as a JSON object for the page.
+    pub fn to_json(&self) -> String {
+        let percent = if self.total_bytes > 0 {
+            self.received_bytes * 100 / self.total_bytes
+        } else {
+            -1
+        };
+        format!(
+            r#"{{"id":{},"state":{},"url":{},"path":{},"mimeType":{},"receivedBytes":{},"totalBytes":{},"percent":{},"endTime":{}}}"#,
+            self.id,
+            json_string(self.state.as_str()),
+            json_string(&self.url),
+            json_string(&self.path.to_string_lossy()),
+            json_string(&self.mime_type),
+            self.received_bytes,
+            self.total_bytes,
+            percent,
+            self.end_time,
+        )
+    }
+
+    /// Returns the tab separated line stored in the history file.
+    fn to_history_line(&self) -> String {
+        [
+            self.end_time.to_string(),
+            self.state.as_str().to_string(),
+            self.received_bytes.to_string(),
+            self.total_bytes.to_string(),
+            escape_field(&self.mime_type),
+            escape_field(&self.url),
+            escape_field(&self.path.to_string_lossy()),
+        ]
+        .join("\t")
+    }
+
+    fn from_history_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let mut next = || fields.next();
+        Some(Self {
+            id: 0,
+            end_time: next()?.parse().ok()?,
+            state: DownloadState::parse(next()?)?,
+            received_bytes: next()?.parse().ok()?,
+            total_bytes: next()?.parse().ok()?,
+            mime_type: unescape_field(next()?),
+            url: unescape_field(next()?),
+            path: unescape_field(next()?).into(),
+        })
+    }
+}
+
+/// Returns the last |HISTORY_SIZE| records of the history file |contents|.
+fn parse_history(contents: &str) -> VecDeque<DownloadRecord> {
+    let mut history = contents
+        .lines()
+        .filter_map(DownloadRecord::from_history_line)
+        .collect::<VecDeque<_>>();
+    while history.len() > HISTORY_SIZE {
+        history.pop_front();
+    }
+    history
+}
+
+fn format_history<'a>(history: impl IntoIterator<Item = &'a DownloadRecord>) -> String {
+    history
+        .into_iter()
+        .map(|record| format!("{}\n", record.to_history_line()))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+struct Download {
+    browser_id: i32,
+    /// The profile of the browser, whose history the download is recorded in.
+    profile: String,
+    record: DownloadRecord,
+    /// The callback of the latest `on_download_updated` call.
+    callback: Option<DownloadItemCallback>,
+    paused: bool,
+}
+
+struct Subscription {
+    browser_id: i32,
+    query_id: i64,
+    callback: Arc<Mutex<dyn BrowserSideCallback>>,
+}
+
+/// Saves downloads according to a `DownloadPolicy` and reports their progress to
+/// the pages that subscribed with `DOWNLOAD_EVENTS_MESSAGE`.
+pub struct DownloadManager {
+    policy: DownloadPolicy,
+    downloads: HashMap<u32, Download>,
+    subscriptions: Vec<Subscription>,
+    /// The last |HISTORY_SIZE| finished downloads of each profile, oldest first.
+    /// Profiles are loaded on first use. The history files are only read and
+    /// written on the file thread.
+    histories: HashMap<String, Arc<Mutex<VecDeque<DownloadRecord>>>>,
+}
+
+impl DownloadManager {
+    pub fn new(policy: DownloadPolicy) -> Self {
+        Self {
+            policy,
+            downloads: HashMap::new(),
+            subscriptions: Vec::new(),
+            histories: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &DownloadPolicy {
+        &self.policy
+    }
+
+    /// Replaces the policy for downloads started from now on. The histories are
+    /// reloaded from the directory of the new policy on first use.
+    pub fn set_policy(&mut self, policy: DownloadPolicy) {
+        if policy.history_dir != self.policy.history_dir {
+            self.histories.clear();
+        }
+        self.policy = policy;
+    }
+
+    /// Returns the downloads that are still running.
+    pub fn active_downloads(&self) -> Vec<DownloadRecord> {
+        self.downloads
+            .values()
+            .map(|download| download.record.clone())
+            .collect()
+    }
+
+    // CefDownloadHandler method
+    pub fn on_before_download(
+        &mut self,
+        browser_id: i32,
+        profile: &str,
+        download_item: &DownloadItem,
+        suggested_name: &str,
+        callback: BeforeDownloadCallback,
+    ) {
+        let mut record = DownloadRecord {
+            id: download_item.id(),
+            url: CefString::from(&download_item.url()).to_string(),
+            path: PathBuf::new(),
+            mime_type: CefString::from(&download_item.mime_type()).to_string(),
+            state: DownloadState::InProgress,
+            received_bytes: 0,
+            total_bytes: download_item.total_bytes(),
+            end_time: 0,
+        };
+
+        let taken = self
+            .downloads
+            .values()
+            .map(|download| download.record.path.as_path())
+            .collect::<Vec<_>>();
+        let Some(path) = self.policy.destination(suggested_name, &taken) else {
+            eprintln!(
+                "blocked the download of {suggested_name} from {}",
+                record.url
+            );
+            record.state = DownloadState::Canceled;
+            record.end_time = now();
+            self.send_event(browser_id, &record);
+            self.append_history(profile, &record);
+            // Releasing |callback| without continuing cancels the download.
+            return;
+        };
+
+        if let Err(err) = fs::create_dir_all(&self.policy.directory) {
+            eprintln!(
+                "failed to create the download directory {}: {err}",
+                self.policy.directory.display()
+            );
+        }
+        callback.cont(
+            Some(&CefString::from(path.to_string_lossy().as_ref())),
+            self.policy.show_dialog.into(),
+        );
+
+        record.path = path;
+        self.send_event(browser_id, &record);
+        self.downloads.insert(
+            record.id,
+            Download {
+                browser_id,
+                profile: profile.to_string(),
+                record,
+                callback: None,
+                paused: false,
+            },
+        );
+    }
+
+    // CefDownloadHandler method
+    pub fn on_download_updated(
+        &mut self,
+        download_item: &DownloadItem,
+        callback: Option<DownloadItemCallback>,
+    ) {
+        let id = download_item.id();
+        let Some(download) = self.downloads.get_mut(&id) else {
+            return;
+        };
+
+        let state = if download_item.is_complete() != 0 {
+            DownloadState::Complete
+        } else if download_item.is_canceled() != 0 {
+            DownloadState::Canceled
+        } else if download_item.is_interrupted() != 0 {
+            DownloadState::Interrupted
+        } else if download.paused {
+            DownloadState::Paused
+        } else {
+            DownloadState::InProgress
+        };
+
+        let old_record = download.record.clone();
+        let record = &mut download.record;
+        record.state = state;
+        record.received_bytes = download_item.received_bytes();
+        record.total_bytes = download_item.total_bytes();
+        // The user may have picked another path in the save dialog.
+        let full_path = CefString::from(&download_item.full_path()).to_string();
+        if !full_path.is_empty() {
+            record.path = full_path.into();
+        }
+        if callback.is_some() {
+            download.callback = callback;
+        }
+
+        if state.is_finished() {
+            let mut download = self.downloads.remove(&id).expect("download was just found");
+            download.record.end_time = now();
+            self.send_event(download.browser_id, &download.record);
+            self.append_history(&download.profile, &download.record);
+            return;
+        }
+
+        // Progress is only reported in whole percents to keep the page responsive.
+        let percent = |record: &DownloadRecord| {
+            (record.total_bytes > 0).then(|| record.received_bytes * 100 / record.total_bytes)
+        };
+        if old_record.state != download.record.state
+            || old_record.path != download.record.path
+            || percent(&old_record) != percent(&download.record)
+            || (download.record.total_bytes <= 0
+                && old_record.received_bytes != download.record.received_bytes)
+        {
+            let (browser_id, record) = (download.browser_id, download.record.clone());
+            self.send_event(browser_id, &record);
+        }
+    }
+
+    /// Pauses the download |id| started by |browser_id|. Fails if there is no such
+    /// download or CEF didn't report it as started yet.
+    pub fn pause(&mut self, browser_id: i32, id: u32) -> Result<(), String> {
+        self.control(browser_id, id, |download, callback| {
+            callback.pause();
+            download.paused = true;
+            download.record.state = DownloadState::Paused;
+            true
+        })
+    }
+
+    pub fn resume(&mut self, browser_id: i32, id: u32) -> Result<(), String> {
+        self.control(browser_id, id, |download, callback| {
+            callback.resume();
+            download.paused = false;
+            download.record.state = DownloadState::InProgress;
+            true
+        })
+    }
+
+    /// Cancels the download |id|. The `canceled` event is sent once CEF reports
+    /// the download as canceled.
+    pub fn cancel(&mut self, browser_id: i32, id: u32) -> Result<(), String> {
+        self.control(browser_id, id, |_, callback| {
+            callback.cancel();
+            false
+        })
+    }
+
+    /// Calls |f| with the download |id| of |browser_id| and its callback. Sends
+    /// the record to the page if |f| returns true.
+    fn control(
+        &mut self,
+        browser_id: i32,
+        id: u32,
+        f: impl FnOnce(&mut Download, &DownloadItemCallback) -> bool,
+    ) -> Result<(), String> {
+        let Some(download) = self
+            .downloads
+            .get_mut(&id)
+            .filter(|download| download.browser_id == browser_id)
+        else {
+            return Err(format!("unknown download {id}"));
+        };
+        let Some(callback) = download.callback.clone() else {
+            return Err(format!("download {id} didn't start yet"));
+        };
+        if f(download, &callback) {
+            let record = download.record.clone();
+            self.send_event(browser_id, &record);
+        }
+        Ok(())
+    }
+
+    /// Sends the download events of |browser_id| to |callback| until the query is
+    /// canceled.
+    pub fn subscribe(
+        &mut self,
+        browser_id: i32,
+        query_id: i64,
+        callback: Arc<Mutex<dyn BrowserSideCallback>>,
+    ) {
+        self.subscriptions.push(Subscription {
+            browser_id,
+            query_id,
+            callback,
+        });
+    }
+
+    pub fn unsubscribe(&mut self, query_id: i64) {
+        self.subscriptions
+            .retain(|subscription| subscription.query_id != query_id);
+    }
+
+    /// Drops the subscriptions of a closing browser. Its downloads keep running.
+    pub fn remove_browser(&mut self, browser_id: i32) {
+        self.subscriptions
+            .retain(|subscription| subscription.browser_id != browser_id);
+    }
+
+    fn send_event(&self, browser_id: i32, record: &DownloadRecord) {
+        let event = record.to_json();
+        for subscription in self
+            .subscriptions
+            .iter()
+            .filter(|subscription| subscription.browser_id == browser_id)
+        {
+            subscription
+                .callback
+                .lock()
+                .expect("Failed to lock callback")
+                .success_str(&event);
+        }
+    }
+
+    /// Returns the file the history of |profile| is stored in.
+    fn history_file(&self, profile: &str) -> Option<PathBuf> {
+        let history_dir = self.policy.history_dir.as_ref()?;
+        Some(history_dir.join(profile).join(HISTORY_FILE_NAME))
+    }
+
+    /// Returns the history of |profile|, starting to load its file on first use.
+    fn profile_history(&mut self, profile: &str) -> Arc<Mutex<VecDeque<DownloadRecord>>> {
+        if let Some(history) = self.histories.get(profile) {
+            return history.clone();
+        }
+        let history = Arc::<Mutex<VecDeque<DownloadRecord>>>::default();
+        if let Some(history_file) = self.history_file(profile) {
+            let mut task = LoadHistory::new(history_file, history.clone());
+            post_task(ThreadId::FILE_USER_VISIBLE, Some(&mut task));
+        }
+        self.histories.insert(profile.to_string(), history.clone());
+        history
+    }
+
+    fn append_history(&mut self, profile: &str, record: &DownloadRecord) {
+        let Some(history_file) = self.history_file(profile) else {
+            return;
+        };
+
+        let history = self.profile_history(profile);
+        let mut history = history.lock().expect("Failed to lock history");
+        history.push_back(record.clone());
+        let mut task = if history.len() > HISTORY_SIZE {
+            // Rewrite the file to drop the oldest entry.
+            history.pop_front();
+            WriteHistory::new(history_file, format_history(history.iter()), false)
+        } else {
+            WriteHistory::new(history_file, format_history([record]), true)
+        };
+        post_task(ThreadId::FILE_USER_VISIBLE, Some(&mut task));
+    }
+
+    /// Returns the last finished downloads of |profile|, oldest first. Downloads
+    /// of earlier sessions are missing until the history file is loaded.
+    pub fn history(&mut self, profile: &str) -> Vec<DownloadRecord> {
+        self.profile_history(profile)
+            .lock()
+            .expect("Failed to lock history")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Empties the history of |profile| and its file.
+    pub fn clear_history(&mut self, profile: &str) {
+        self.profile_history(profile)
+            .lock()
+            .expect("Failed to lock history")
+            .clear();
+        if let Some(history_file) = self.history_file(profile) {
+            let mut task = WriteHistory::new(history_file, String::new(), false);
+            post_task(ThreadId::FILE_USER_VISIBLE, Some(&mut task));
+        }
+    }
+}
+
+fn write_history(history_file: &Path, contents: &str, append: bool) {
+    if let Some(parent) = history_file.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let result = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(history_file)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+    if let Err(err) = result {
+        eprintln!(
+            "failed to write the download history {}: {err}",
+            history_file.display()
+        );
+    }
+}
+
+wrap_task! {
+    struct LoadHistory {
+        history_file: PathBuf,
+        history: Arc<Mutex<VecDeque<DownloadRecord>>>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            let Ok(contents) = fs::read_to_string(&self.history_file) else {
+                return;
+            };
+            let loaded = parse_history(&contents);
+            if loaded.len() < contents.lines().count() {
+                // Drop the entries beyond |HISTORY_SIZE| and unreadable lines.
+                write_history(&self.history_file, &format_history(&loaded), false);
+            }
+
+            // Downloads may have finished while the file was loading.
+            let mut history = self.history.lock().expect("Failed to lock history");
+            let finished = std::mem::replace(&mut *history, loaded);
+            history.extend(finished);
+            while history.len() > HISTORY_SIZE {
+                history.pop_front();
+            }
+        }
+    }
+}
+
+wrap_task! {
+    struct WriteHistory {
+        history_file: PathBuf,
+        contents: String,
+        append: bool,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            write_history(&self.history_file, &self.contents, self.append);
+        }
+    }
+}
+
+/// Answers the download queries of the main frame. Commands only apply to the
+/// downloads of the querying browser.
+pub struct DownloadMessageHandler {
+    downloads: Arc<Mutex<DownloadManager>>,
+}
+
+impl DownloadMessageHandler {
+    pub fn new(downloads: Arc<Mutex<DownloadManager>>) -> Self {
+        Self { downloads }
+    }
+}
+
+impl std::fmt::Debug for DownloadMessageHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DownloadMessageHandler")
+            .finish_non_exhaustive()
+    }
+}
+
+impl BrowserSideHandler for DownloadMessageHandler {
+    fn on_query_str(
+        &self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        query_id: i64,
+        request: &str,
+        persistent: bool,
+        callback: Arc<Mutex<dyn BrowserSideCallback>>,
+    ) -> bool {
+        if request != DOWNLOAD_EVENTS_MESSAGE
+            && request != DOWNLOAD_HISTORY_MESSAGE
+            && !request.starts_with(DOWNLOAD_PAUSE_PREFIX)
+            && !request.starts_with(DOWNLOAD_RESUME_PREFIX)
+            && !request.starts_with(DOWNLOAD_CANCEL_PREFIX)
+        {
+            return false;
+        }
+        let Some(browser) = browser else {
+            return false;
+        };
+        let browser_id = browser.identifier();
+        let mut downloads = self.downloads.lock().expect("Failed to lock downloads");
+
+        // Only the main frame of the app pages may watch and control downloads.
+        let is_ui_frame = frame.is_some_and(|frame| {
+            let url = CefString::from(&frame.url()).to_string();
+            frame.is_main() != 0 && has_origin(&url, &downloads.policy().ui_origins)
+        });
+        if !is_ui_frame {
+            callback
+                .lock()
+                .expect("Failed to lock callback")
+                .failure(0, "download queries are limited to the UI origins");
+            return true;
+        }
+
+        if request == DOWNLOAD_EVENTS_MESSAGE {
+            if persistent {
+                downloads.subscribe(browser_id, query_id, callback);
+            } else {
+                callback
+                    .lock()
+                    .expect("Failed to lock callback")
+                    .failure(0, "DownloadEvents must be a persistent query");
+            }
+            return true;
+        }
+
+        if request == DOWNLOAD_HISTORY_MESSAGE {
+            let history = downloads
+                .history(&get_profile_name(&browser))
+                .iter()
+                .map(DownloadRecord::to_json)
+                .collect::<Vec<_>>()
+                .join(",");
+            callback
+                .lock()
+                .expect("Failed to lock callback")
+                .success_str(&format!("[{history}]"));
+            return true;
+        }
+
+        type Command = fn(&mut DownloadManager, i32, u32) -> Result<(), String>;
+        let (id, command): (&str, Command) =
+            if let Some(id) = request.strip_prefix(DOWNLOAD_PAUSE_PREFIX) {
+                (id, DownloadManager::pause)
+            } else if let Some(id) = request.strip_prefix(DOWNLOAD_RESUME_PREFIX) {
+                (id, DownloadManager::resume)
+            } else if let Some(id) = request.strip_prefix(DOWNLOAD_CANCEL_PREFIX) {
+                (id, DownloadManager::cancel)
+            } else {
+                return false;
+            };
+
+        let result = id
+            .parse()
+            .map_err(|_| format!("unknown download {id}"))
+            .and_then(|id| command(&mut downloads, browser_id, id));
+        let mut callback = callback.lock().expect("Failed to lock callback");
+        match result {
+            Ok(()) => callback.success_str(""),
+            Err(err) => callback.failure(0, &err),
+        }
+        true
+    }
+
+    fn on_query_canceled(&self, _browser: Option<Browser>, _frame: Option<Frame>, query_id: i64) {
+        self.downloads
+            .lock()
+            .expect("Failed to lock downloads")
+            .unsubscribe(query_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(url: &str) -> DownloadRecord {
+        DownloadRecord {
+            id: 0,
+            url: url.to_string(),
+            path: PathBuf::from("/tmp/a\tb\\c\nd.txt"),
+            mime_type: "text/plain".to_string(),
+            state: DownloadState::Complete,
+            received_bytes: 10,
+            total_bytes: -1,
+            end_time: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn sanitize() {
+        assert_eq!(sanitize_file_name("report.pdf"), "report.pdf");
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("..\\\\evil.exe"), "evil.exe");
+        assert_eq!(sanitize_file_name("a<b>:c|d?.txt"), "a_b__c_d_.txt");
+        assert_eq!(sanitize_file_name(" .hidden. "), "hidden");
+        assert_eq!(sanitize_file_name(""), "download");
+        assert_eq!(sanitize_file_name(".."), "download");
+    }
+
+    #[test]
+    fn destination() {
+        let directory = std::env::temp_dir().join(format!(
+            "message_router_download_test_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("file.txt"), "").unwrap();
+        let policy = DownloadPolicy {
+            directory: directory.clone(),
+            blocked_extensions: vec!["exe".to_string()],
+            history_dir: None,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            policy.destination("new.txt", &[]),
+            Some(directory.join("new.txt"))
+        );
+        assert_eq!(
+            policy.destination("file.txt", &[]),
+            Some(directory.join("file (1).txt"))
+        );
+        let taken = directory.join("file (1).txt");
+        assert_eq!(
+            policy.destination("file.txt", &[taken.as_path()]),
+            Some(directory.join("file (2).txt"))
+        );
+        assert_eq!(
+            policy.destination("../file", &[]),
+            Some(directory.join("file"))
+        );
+        assert_eq!(policy.destination("setup.EXE", &[]), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn history_file_per_profile() {
+        let directory = PathBuf::from("/data/profiles");
+        let manager = DownloadManager::new(DownloadPolicy {
+            history_dir: Some(directory.clone()),
+            ..Default::default()
+        });
+        assert_eq!(
+            manager.history_file("work"),
+            Some(directory.join("work").join(HISTORY_FILE_NAME))
+        );
+        assert_ne!(manager.history_file("work"), manager.history_file("home"));
+
+        let manager = DownloadManager::new(DownloadPolicy {
+            history_dir: None,
+            ..Default::default()
+        });
+        assert_eq!(manager.history_file("work"), None);
+    }
+
+    #[test]
+    fn history_round_trip() {
+        let records = [
+            record("https://example.com/a"),
+            record("https://example.com/b"),
+        ];
+        let contents = format_history(&records);
+        assert_eq!(contents.lines().count(), 2);
+        assert_eq!(parse_history(&contents), records);
+        assert!(parse_history("garbage\n").is_empty());
+    }
+
+    #[test]
+    fn history_is_limited() {
+        let records = (0..HISTORY_SIZE + 5)
+            .map(|n| record(&format!("https://example.com/{n}")))
+            .collect::<Vec<_>>();
+        let history = parse_history(&format_history(&records));
+        assert_eq!(history.len(), HISTORY_SIZE);
+        assert_eq!(history.front(), records.get(5));
+    }
+}
@@ -0,0 +1,442 @@
+use std::{
+    fs::File,
+    io::{self, Cursor, Read},
+    os::raw::c_int,
+    path::PathBuf,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+};
+
+use cef::*;
+
+use crate::shared::{
+    local_resource_handler::status_text, resource_util::get_url_without_query_or_fragment,
+};
+
+/// Network error passed to `ResourceReadCallback::cont` if a stream fails
+/// (`ERR_FAILED`).
+const ERR_FAILED: c_int = -2;
+
+/// A request answered by a `ContentHandler`.
+#[derive(Clone, Default)]
+pub struct ContentRequest {
+    /// Upper case method, e.g. `GET` or `POST`.
+    pub method: String,
+    pub url: String,
+    /// The query without the leading `?`.
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    /// Profile of the browser that sent the request, see
+    /// `profile::get_profile_name`.
+    pub profile: String,
+    /// Files of the request body, e.g. from a file upload. `body` streams them
+    /// from disk.
+    pub files: Vec<PathBuf>,
+    body_elements: Vec<PostDataElement>,
+}
+
+impl ContentRequest {
+    /// Returns the first header called |name|, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns a reader for the request body, starting at its beginning.
+    pub fn body(&self) -> RequestBody {
+        RequestBody {
+            elements: self.body_elements.clone().into_iter(),
+            current: None,
+        }
+    }
+}
+
+/// Reads the body of a `ContentRequest` element by element. Byte elements are
+/// copied when they are reached and file elements are read from disk, so large
+/// uploads are never held in memory.
+pub struct RequestBody {
+    elements: std::vec::IntoIter<PostDataElement>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl Read for RequestBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(current) = self.current.as_mut() {
+                let read = current.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+                self.current = None;
+            }
+
+            let Some(element) = self.elements.next() else {
+                return Ok(0);
+            };
+            let element_type = element.get_type();
+            self.current = if element_type == PostdataelementType::BYTES {
+                let mut data = vec![0; element.bytes_count()];
+                let read = element.bytes(data.len(), data.as_mut_ptr().cast());
+                data.truncate(read);
+                Some(Box::new(Cursor::new(data)))
+            } else if element_type == PostdataelementType::FILE {
+                let path = CefString::from(&element.file()).to_string();
+                Some(Box::new(File::open(path)?))
+            } else {
+                None
+            };
+        }
+    }
+}
+
+pub enum ContentBody {
+    Bytes(Vec<u8>),
+    /// Read on a thread of its own while the response is sent, for large or
+    /// generated bodies.
+    Stream(Box<dyn Read + Send>),
+}
+
+/// The response of a `ContentHandler`.
+pub struct ContentResponse {
+    pub status: i32,
+    pub mime_type: String,
+    pub headers: Vec<(String, String)>,
+    pub body: ContentBody,
+}
+
+impl ContentResponse {
+    pub fn new(status: i32, mime_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            mime_type: mime_type.to_string(),
+            headers: Vec::new(),
+            body: ContentBody::Bytes(body),
+        }
+    }
+
+    pub fn text(text: &str) -> Self {
+        Self::new(200, "text/plain", text.as_bytes().to_vec())
+    }
+
+    pub fn json(json: &str) -> Self {
+        Self::new(200, "application/json", json.as_bytes().to_vec())
+    }
+
+    pub fn stream(mime_type: &str, reader: Box<dyn Read + Send>) -> Self {
+        Self {
+            status: 200,
+            mime_type: mime_type.to_string(),
+            headers: Vec::new(),
+            body: ContentBody::Stream(reader),
+        }
+    }
+
+    pub fn with_status(mut self, status: i32) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Produces the response to a request. Handlers are called on the IO thread, so
+/// long running work should be done in a stream, which is read on a thread of
+/// its own.
+pub type ContentHandler = Arc<dyn Fn(&ContentRequest) -> ContentResponse + Send + Sync>;
+
+struct ContentRoute {
+    /// Only requests with this method are answered, any method if `None`.
+    method: Option<String>,
+    url: String,
+    handler: ContentHandler,
+}
+
+impl ContentRoute {
+    fn matches(&self, method: &str, url: &str) -> bool {
+        let url_matches = match self.url.strip_suffix('*') {
+            Some(prefix) => url.starts_with(prefix),
+            None => url == self.url,
+        };
+        url_matches
+            && self
+                .method
+                .as_ref()
+                .is_none_or(|route_method| route_method.eq_ignore_ascii_case(method))
+    }
+}
+
+/// Maps URLs to handlers generating their content.
+#[derive(Default)]
+pub struct ContentRoutes {
+    routes: Vec<ContentRoute>,
+}
+
+impl ContentRoutes {
+    /// Answers requests to |url| with |handler|. |url| excludes the query; a
+    /// trailing `*` matches any URL starting with the rest of |url|. Routes added
+    /// later take precedence.
+    pub fn add(&mut self, method: Option<&str>, url: &str, handler: ContentHandler) {
+        self.routes.insert(
+            0,
+            ContentRoute {
+                method: method.map(str::to_string),
+                url: url.to_string(),
+                handler,
+            },
+        );
+    }
+
+    /// Removes the routes for |method| and |url|, as passed to `add`. Returns false
+    /// if there were none.
+    pub fn remove(&mut self, method: Option<&str>, url: &str) -> bool {
+        let len = self.routes.len();
+        self.routes.retain(|route| {
+            let same_method = match (route.method.as_deref(), method) {
+                (Some(route_method), Some(method)) => route_method.eq_ignore_ascii_case(method),
+                (route_method, method) => route_method == method,
+            };
+            !(same_method && route.url == url)
+        });
+        self.routes.len() != len
+    }
+
+    /// Returns the handler for |request|, if any.
+    pub fn find(&self, request: &Request) -> Option<ContentHandler> {
+        let method = CefString::from(&request.method()).to_string();
+        let url = get_url_without_query_or_fragment(&CefString::from(&request.url()).to_string());
+        self.routes
+            .iter()
+            .find(|route| route.matches(&method, &url))
+            .map(|route| route.handler.clone())
+    }
+}
+
+/// Returns the contents of |request| for a `ContentHandler`.
+fn get_content_request(request: &Request, profile: &str) -> ContentRequest {
+    let url = CefString::from(&request.url()).to_string();
+    let query = url
+        .split_once('?')
+        .map(|(_, query)| query.split('#').next().unwrap_or_default().to_string())
+        .unwrap_or_default();
+
+    let mut header_map = CefStringMultimap::new();
+    request.header_map(Some(&mut header_map));
+    let headers = Vec::<(String, String)>::from(&header_map);
+
+    let mut body_elements = Vec::new();
+    if let Some(post_data) = request.post_data() {
+        let mut elements = Vec::with_capacity(post_data.element_count());
+        post_data.elements(Some(&mut elements));
+        body_elements.extend(elements.into_iter().flatten());
+    }
+    let files = body_elements
+        .iter()
+        .filter(|element| element.get_type() == PostdataelementType::FILE)
+        .map(|element| PathBuf::from(CefString::from(&element.file()).to_string()))
+        .collect();
+
+    ContentRequest {
+        method: CefString::from(&request.method())
+            .to_string()
+            .to_ascii_uppercase(),
+        url,
+        query,
+        headers,
+        profile: profile.to_string(),
+        files,
+        body_elements,
+    }
+}
+
+/// A pending asynchronous read of a streamed response.
+struct StreamRead {
+    data_out: *mut u8,
+    len: usize,
+    callback: ResourceReadCallback,
+}
+
+// SAFETY: CEF keeps |data_out| valid until |callback| is executed, and only the
+// stream thread accesses it meanwhile.
+unsafe impl Send for StreamRead {}
+
+/// Reads |reader| on a thread of its own, so that slow streams don't block the IO
+/// thread. The thread ends when the returned sender is dropped.
+fn spawn_stream_reader(mut reader: Box<dyn Read + Send>) -> mpsc::Sender<StreamRead> {
+    let (sender, receiver) = mpsc::channel::<StreamRead>();
+    thread::spawn(move || {
+        for read in receiver {
+            // SAFETY: See `StreamRead`.
+            let buffer = unsafe { std::slice::from_raw_parts_mut(read.data_out, read.len) };
+            let size = loop {
+                match reader.read(buffer) {
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => {
+                        eprintln!("failed to read a streamed response: {err}");
+                        break ERR_FAILED;
+                    }
+                    Ok(size) => break size as c_int,
+                }
+            };
+            read.callback.cont(size);
+        }
+    });
+    sender
+}
+
+enum State {
+    Pending,
+    Responding {
+        response: ContentResponse,
+        offset: usize,
+        /// Set for `ContentBody::Stream` responses.
+        stream: Option<mpsc::Sender<StreamRead>>,
+    },
+}
+
+/// Returns a handler answering requests of a browser using |profile| with the
+/// response of |handler|.
+pub fn create_content_resource_handler(
+    handler: ContentHandler,
+    profile: String,
+) -> ResourceHandler {
+    ContentResourceHandler::new(handler, profile, Arc::new(Mutex::new(State::Pending)))
+}
+
+wrap_resource_handler! {
+    struct ContentResourceHandler {
+        handler: ContentHandler,
+        profile: String,
+        state: Arc<Mutex<State>>,
+    }
+
+    impl ResourceHandler {
+        fn open(
+            &self,
+            request: Option<&mut Request>,
+            handle_request: Option<&mut c_int>,
+            _callback: Option<&mut Callback>,
+        ) -> c_int {
+            let (Some(request), Some(handle_request)) = (request, handle_request) else {
+                return 0;
+            };
+            *handle_request = 1;
+
+            let mut response = (self.handler)(&get_content_request(request, &self.profile));
+            let body = std::mem::replace(&mut response.body, ContentBody::Bytes(Vec::new()));
+            let stream = match body {
+                ContentBody::Stream(reader) => Some(spawn_stream_reader(reader)),
+                body => {
+                    response.body = body;
+                    None
+                }
+            };
+            *self.state.lock().expect("Failed to lock state") = State::Responding {
+                response,
+                offset: 0,
+                stream,
+            };
+            1
+        }
+
+        fn response_headers(
+            &self,
+            response: Option<&mut Response>,
+            response_length: Option<&mut i64>,
+            _redirect_url: Option<&mut CefString>,
+        ) {
+            let state = self.state.lock().expect("Failed to lock state");
+            let (
+                Some(response),
+                State::Responding {
+                    response: content,
+                    stream,
+                    ..
+                },
+            ) = (response, &*state)
+            else {
+                return;
+            };
+
+            response.set_status(content.status);
+            response.set_status_text(Some(&CefString::from(status_text(content.status))));
+            response.set_mime_type(Some(&CefString::from(content.mime_type.as_str())));
+            for (name, value) in &content.headers {
+                response.set_header_by_name(
+                    Some(&CefString::from(name.as_str())),
+                    Some(&CefString::from(value.as_str())),
+                    1,
+                );
+            }
+
+            if let Some(response_length) = response_length {
+                *response_length = match (&content.body, stream) {
+                    (ContentBody::Bytes(body), None) => body.len() as i64,
+                    _ => -1,
+                };
+            }
+        }
+
+        fn read(
+            &self,
+            data_out: *mut u8,
+            bytes_to_read: c_int,
+            bytes_read: Option<&mut c_int>,
+            callback: Option<&mut ResourceReadCallback>,
+        ) -> c_int {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            let State::Responding {
+                response,
+                offset,
+                stream,
+            } = &mut *state
+            else {
+                return 0;
+            };
+
+            if let Some(stream) = stream.as_ref() {
+                // Read asynchronously, the stream thread executes |callback|.
+                let Some(callback) = callback.cloned() else {
+                    return 0;
+                };
+                let read = StreamRead {
+                    data_out,
+                    len: bytes_to_read.max(0) as usize,
+                    callback,
+                };
+                if stream.send(read).is_err() {
+                    return 0;
+                }
+                if let Some(bytes_read) = bytes_read {
+                    *bytes_read = 0;
+                }
+                return 1;
+            }
+
+            let ContentBody::Bytes(body) = &response.body else {
+                return 0;
+            };
+            let size = (body.len() - *offset).min(bytes_to_read.max(0) as usize);
+            if size > 0 {
+                // SAFETY: CEF provides a buffer of at least |bytes_to_read| bytes.
+                unsafe {
+                    std::ptr::copy_nonoverlapping(body[*offset..].as_ptr(), data_out, size);
+                }
+                *offset += size;
+            }
+
+            if let Some(bytes_read) = bytes_read {
+                *bytes_read = size as c_int;
+            }
+            (size > 0).into()
+        }
+
+        fn cancel(&self) {
+            *self.state.lock().expect("Failed to lock state") = State::Pending;
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    os::raw::c_int,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use cef::*;
+
+use crate::shared::local_resource_handler::status_text;
+
+/// Parses a `Range` header value for a resource of |size| bytes.
+///
+/// Returns `Ok(None)` if the whole resource should be served, i.e. the header is
+/// absent, malformed or uses a form that isn't supported (multiple ranges), and
+/// `Err(())` if the range can't be satisfied.
+pub fn parse_range(header: Option<&str>, size: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        // Multipart ranges are not supported, serve everything instead.
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // Suffix range: the last |end| bytes.
+        let Ok(length) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        if length == 0 || size == 0 {
+            return Err(());
+        }
+        return Ok(Some((size.saturating_sub(length), size - 1)));
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return Ok(None);
+    };
+    let end = if end.is_empty() {
+        size.saturating_sub(1)
+    } else {
+        let Ok(end) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        if end < start {
+            return Ok(None);
+        }
+        end.min(size.saturating_sub(1))
+    };
+    if start >= size {
+        return Err(());
+    }
+
+    Ok(Some((start, end)))
+}
+
+struct FileState {
+    file: Option<File>,
+    status: i32,
+    size: u64,
+    /// Inclusive byte range being served.
+    range: Option<(u64, u64)>,
+    remaining: u64,
+}
+
+/// Returns a handler streaming the file at |path| from disk. `Range` requests are
+/// answered with `206 Partial Content`, so media elements can seek.
+pub fn create_file_resource_handler(path: PathBuf, mime_type: String) -> ResourceHandler {
+    let state = FileState {
+        file: None,
+        status: 200,
+        size: 0,
+        range: None,
+        remaining: 0,
+    };
+    FileResourceHandler::new(path, mime_type, Arc::new(Mutex::new(state)))
+}
+
+wrap_resource_handler! {
+    struct FileResourceHandler {
+        path: PathBuf,
+        mime_type: String,
+        state: Arc<Mutex<FileState>>,
+    }
+
+    impl ResourceHandler {
+        fn open(
+            &self,
+            request: Option<&mut Request>,
+            handle_request: Option<&mut c_int>,
+            _callback: Option<&mut Callback>,
+        ) -> c_int {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            if let Some(handle_request) = handle_request {
+                *handle_request = 1;
+            }
+
+            let Ok(mut file) = File::open(&self.path) else {
+                return 0;
+            };
+            let Ok(size) = file.metadata().map(|metadata| metadata.len()) else {
+                return 0;
+            };
+            state.size = size;
+
+            let range_header = request
+                .as_ref()
+                .map(|request| {
+                    CefString::from(&request.header_by_name(Some(&CefString::from("Range"))))
+                        .to_string()
+                });
+            match parse_range(range_header.as_deref(), size) {
+                Ok(Some((start, end))) => {
+                    if file.seek(SeekFrom::Start(start)).is_err() {
+                        return 0;
+                    }
+                    state.status = 206;
+                    state.range = Some((start, end));
+                    state.remaining = end - start + 1;
+                }
+                Ok(None) => {
+                    state.status = 200;
+                    state.remaining = size;
+                }
+                Err(()) => {
+                    state.status = 416;
+                    state.remaining = 0;
+                }
+            }
+            state.file = Some(file);
+
+            1
+        }
+
+        fn response_headers(
+            &self,
+            response: Option<&mut Response>,
+            response_length: Option<&mut i64>,
+            _redirect_url: Option<&mut CefString>,
+        ) {
+            let state = self.state.lock().expect("Failed to lock state");
+            let Some(response) = response else {
+                return;
+            };
+
+            let set_header = |name: &str, value: &str| {
+                response.set_header_by_name(
+                    Some(&CefString::from(name)),
+                    Some(&CefString::from(value)),
+                    1,
+                );
+            };
+            set_header("Accept-Ranges", "bytes");
+            match state.range {
+                Some((start, end)) if state.status == 206 => {
+                    set_header("Content-Range", &format!("bytes {start}-{end}/{}", state.size));
+                }
+                _ if state.status == 416 => {
+                    set_header("Content-Range", &format!("bytes */{}", state.size));
+                }
+                _ => {}
+            }
+
+            response.set_status(state.status);
+            response.set_status_text(Some(&CefString::from(status_text(state.status))));
+            response.set_mime_type(Some(&CefString::from(self.mime_type.as_str())));
+
+            if let Some(response_length) = response_length {
+                *response_length = state.remaining as i64;
+            }
+        }
+
+        fn read(
+            &self,
+            data_out: *mut u8,
+            bytes_to_read: c_int,
+            bytes_read: Option<&mut c_int>,
+            _callback: Option<&mut ResourceReadCallback>,
+        ) -> c_int {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            let size = state.remaining.min(bytes_to_read.max(0) as u64) as usize;
+
+            let mut read = 0;
+            if size > 0
+                && let Some(file) = state.file.as_mut()
+            {
+                // SAFETY: CEF provides a buffer of at least |bytes_to_read| bytes.
+                let buffer = unsafe { std::slice::from_raw_parts_mut(data_out, size) };
+                read = file.read(buffer).unwrap_or(0);
+            }
+            state.remaining -= read as u64;
+
+            if let Some(bytes_read) = bytes_read {
+                *bytes_read = read as c_int;
+            }
+            (read > 0).into()
+        }
+
+        fn cancel(&self) {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            state.file = None;
+            state.remaining = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range(Some("bytes=0-99"), 1000), Ok(Some((0, 99))));
+        assert_eq!(
+            parse_range(Some(" bytes=10 - 20 "), 1000),
+            Ok(Some((10, 20)))
+        );
+        assert_eq!(
+            parse_range(Some("bytes=900-2000"), 1000),
+            Ok(Some((900, 999)))
+        );
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(parse_range(Some("bytes=-500"), 1000), Ok(Some((500, 999))));
+        assert_eq!(parse_range(Some("bytes=-2000"), 1000), Ok(Some((0, 999))));
+        assert_eq!(parse_range(Some("bytes=-0"), 1000), Err(()));
+        assert_eq!(parse_range(Some("bytes=-10"), 0), Err(()));
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        assert_eq!(parse_range(Some("bytes=100-"), 1000), Ok(Some((100, 999))));
+        assert_eq!(parse_range(Some("bytes=999-"), 1000), Ok(Some((999, 999))));
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), 1000), Ok(None));
+        assert_eq!(parse_range(Some("bytes=-5, 10-"), 1000), Ok(None));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert_eq!(parse_range(Some("bytes=1000-"), 1000), Err(()));
+        assert_eq!(parse_range(Some("bytes=1000-1200"), 1000), Err(()));
+        assert_eq!(parse_range(Some("bytes=0-"), 0), Err(()));
+    }
+
+    #[test]
+    fn malformed_ranges() {
+        for header in [
+            "items=0-1",
+            "bytes=",
+            "bytes=abc",
+            "bytes=a-b",
+            "bytes=1-a",
+            "bytes=5-1",
+            "bytes=--1",
+        ] {
+            assert_eq!(parse_range(Some(header), 1000), Ok(None), "{header}");
+        }
+        assert_eq!(parse_range(None, 1000), Ok(None));
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    os::raw::c_int,
+    sync::{Arc, Mutex},
+};
+
+use cef::*;
+
+use crate::shared::security_policy::{SecurityPolicy, generate_nonce, insert_nonce};
+
+/// Generates the HTML of the error page for a status code and request URL.
+pub type ErrorPage = Arc<dyn Fn(i32, &str) -> String + Send + Sync>;
+
+/// How a mount answers requests.
+#[derive(Clone, Default)]
+pub struct ResponsePolicy {
+    /// Headers added to every response, e.g. `Cache-Control` or CORS headers.
+    pub headers: Vec<(String, String)>,
+    /// Replaces the built-in error page.
+    pub error_page: Option<ErrorPage>,
+    /// Content Security Policy and other security headers.
+    pub security: Option<SecurityPolicy>,
+}
+
+impl ResponsePolicy {
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_error_page(mut self, error_page: ErrorPage) -> Self {
+        self.error_page = Some(error_page);
+        self
+    }
+
+    pub fn with_security_policy(mut self, security: SecurityPolicy) -> Self {
+        self.security = Some(security);
+        self
+    }
+
+    fn error_page(&self, status: i32, url: &str) -> String {
+        match self.error_page.as_ref() {
+            Some(error_page) => error_page(status, url),
+            None => default_error_page(status),
+        }
+    }
+}
+
+const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+pub fn status_text(status: i32) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        416 => "Range Not Satisfiable",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+fn default_error_page(status: i32) -> String {
+    let text = status_text(status);
+    format!(
+        "<html>\n<head>\n<title>{status} {text}</title>\n</head>\n\
+         <body bgcolor=\"white\">\n<h2>{status} {text}</h2>\n</body>\n</html>\n"
+    )
+}
+
+/// What a `LocalResourceHandler` answers with.
+pub enum LocalResponse {
+    /// Delegate to the handler that serves the resource.
+    Resource(ResourceHandler),
+    /// The resource exists but may not be served.
+    Forbidden,
+    /// The resource doesn't exist.
+    NotFound,
+    /// The request was handled without a response body, e.g. a violation report.
+    NoContent,
+}
+
+enum State {
+    Pending(LocalResponse),
+    /// Serving the delegate. |head_only| suppresses the body. HTML pages are read
+    /// into |html| to replace the nonce placeholder.
+    Delegate {
+        handler: ResourceHandler,
+        head_only: bool,
+        html: Option<Buffer>,
+    },
+    /// Serving a generated response.
+    Generated {
+        status: i32,
+        mime_type: &'static str,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        offset: usize,
+    },
+}
+
+#[derive(Default)]
+struct Buffer {
+    data: Vec<u8>,
+    offset: usize,
+    complete: bool,
+}
+
+impl State {
+    fn generated(status: i32, body: Vec<u8>) -> Self {
+        Self::Generated {
+            status,
+            mime_type: "text/html",
+            headers: Vec::new(),
+            body,
+            offset: 0,
+        }
+    }
+}
+
+/// Returns a handler answering local resource requests with proper HTTP semantics:
+/// 404/403 error pages, 405 for unsupported methods, `HEAD` and `OPTIONS`
+/// handling, and the headers of |policy| on every response. |prefix| is the
+/// mount the request belongs to.
+pub fn create_local_resource_handler(
+    response: LocalResponse,
+    policy: ResponsePolicy,
+    prefix: &str,
+) -> ResourceHandler {
+    let nonce = policy
+        .security
+        .as_ref()
+        .filter(|security| security.uses_nonce())
+        .map(|_| generate_nonce());
+    let mut headers = policy.headers.clone();
+    if let Some(security) = policy.security.as_ref() {
+        headers.extend(security.headers(prefix, nonce.as_deref()));
+    }
+
+    LocalResourceHandler::new(
+        Arc::new(Mutex::new(State::Pending(response))),
+        policy,
+        headers,
+        nonce,
+    )
+}
+
+/// Reads the complete body of |handler|. Returns false if the handler failed.
+fn read_all(handler: &ResourceHandler, buffer: &mut Vec<u8>) -> bool {
+    let mut chunk = vec![0u8; 65536];
+    loop {
+        let mut bytes_read = 0;
+        let ret = handler.read(
+            chunk.as_mut_ptr(),
+            chunk.len() as c_int,
+            Some(&mut bytes_read),
+            None,
+        );
+        if ret == 0 || bytes_read <= 0 {
+            // A negative count is an error, zero the end of the body.
+            return bytes_read >= 0;
+        }
+        buffer.extend_from_slice(&chunk[..bytes_read as usize]);
+    }
+}
+
+wrap_resource_handler! {
+    struct LocalResourceHandler {
+        state: Arc<Mutex<State>>,
+        policy: ResponsePolicy,
+        // The policy and security headers added to every response.
+        headers: Vec<(String, String)>,
+        // Replaces `CSP_NONCE_PLACEHOLDER` in HTML pages.
+        nonce: Option<String>,
+    }
+
+    impl ResourceHandler {
+        fn open(
+            &self,
+            request: Option<&mut Request>,
+            handle_request: Option<&mut c_int>,
+            callback: Option<&mut Callback>,
+        ) -> c_int {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            let (method, url) = request
+                .as_ref()
+                .map(|request| {
+                    (
+                        CefString::from(&request.method()).to_string().to_ascii_uppercase(),
+                        CefString::from(&request.url()).to_string(),
+                    )
+                })
+                .unwrap_or_default();
+
+            let State::Pending(response) =
+                std::mem::replace(&mut *state, State::generated(500, Vec::new()))
+            else {
+                return 0;
+            };
+
+            let next_state = match (method.as_str(), response) {
+                (_, LocalResponse::NoContent) => State::Generated {
+                    status: 204,
+                    mime_type: "text/plain",
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                    offset: 0,
+                },
+                ("OPTIONS", _) => State::Generated {
+                    status: 204,
+                    mime_type: "text/plain",
+                    headers: vec![("Allow".to_string(), ALLOWED_METHODS.to_string())],
+                    body: Vec::new(),
+                    offset: 0,
+                },
+                ("GET" | "HEAD" | "", LocalResponse::Resource(handler)) => {
+                    // The delegate decides whether it handles the request synchronously.
+                    let ret = handler.open(request, handle_request, callback);
+                    *state = State::Delegate {
+                        handler,
+                        head_only: method == "HEAD",
+                        html: None,
+                    };
+                    return ret;
+                }
+                ("GET" | "HEAD" | "", response) => {
+                    let status = if matches!(response, LocalResponse::Forbidden) { 403 } else { 404 };
+                    let mut body = self.policy.error_page(status, &url).into_bytes();
+                    if method == "HEAD" {
+                        body.clear();
+                    }
+                    State::generated(status, body)
+                }
+                _ => {
+                    let mut state = State::generated(405, self.policy.error_page(405, &url).into_bytes());
+                    if let State::Generated { headers, .. } = &mut state {
+                        headers.push(("Allow".to_string(), ALLOWED_METHODS.to_string()));
+                    }
+                    state
+                }
+            };
+            *state = next_state;
+
+            if let Some(handle_request) = handle_request {
+                *handle_request = 1;
+            }
+            1
+        }
+
+        fn response_headers(
+            &self,
+            response: Option<&mut Response>,
+            response_length: Option<&mut i64>,
+            redirect_url: Option<&mut CefString>,
+        ) {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            let Some(response) = response else {
+                return;
+            };
+
+            match &mut *state {
+                State::Delegate {
+                    handler,
+                    head_only,
+                    html,
+                } => {
+                    let mut length = -1;
+                    handler.response_headers(Some(&mut *response), Some(&mut length), redirect_url);
+
+                    let mime_type = CefString::from(&response.mime_type()).to_string();
+                    if self.nonce.is_some() && !*head_only && mime_type == "text/html" {
+                        // The length changes when the placeholder is replaced.
+                        *html = Some(Buffer::default());
+                        length = -1;
+                    }
+                    if let Some(response_length) = response_length {
+                        *response_length = if *head_only { 0 } else { length };
+                    }
+                }
+                State::Generated {
+                    status,
+                    mime_type,
+                    headers,
+                    body,
+                    ..
+                } => {
+                    response.set_status(*status);
+                    response.set_status_text(Some(&CefString::from(status_text(*status))));
+                    response.set_mime_type(Some(&CefString::from(*mime_type)));
+                    for (name, value) in headers {
+                        response.set_header_by_name(
+                            Some(&CefString::from(name.as_str())),
+                            Some(&CefString::from(value.as_str())),
+                            1,
+                        );
+                    }
+                    if let Some(response_length) = response_length {
+                        *response_length = body.len() as i64;
+                    }
+                }
+                State::Pending(_) => return,
+            }
+
+            for (name, value) in &self.headers {
+                response.set_header_by_name(
+                    Some(&CefString::from(name.as_str())),
+                    Some(&CefString::from(value.as_str())),
+                    1,
+                );
+            }
+        }
+
+        fn read(
+            &self,
+            data_out: *mut u8,
+            bytes_to_read: c_int,
+            bytes_read: Option<&mut c_int>,
+            callback: Option<&mut ResourceReadCallback>,
+        ) -> c_int {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            match &mut *state {
+                State::Delegate { head_only: true, .. } => {
+                    if let Some(bytes_read) = bytes_read {
+                        *bytes_read = 0;
+                    }
+                    0
+                }
+                State::Delegate {
+                    handler,
+                    html: Some(html),
+                    ..
+                } => {
+                    if !html.complete {
+                        html.complete = true;
+                        let mut data = Vec::new();
+                        if !read_all(handler, &mut data) {
+                            return 0;
+                        }
+                        html.data = match String::from_utf8(data) {
+                            Ok(page) => {
+                                insert_nonce(&page, self.nonce.as_deref().unwrap_or_default())
+                                    .into_bytes()
+                            }
+                            Err(err) => err.into_bytes(),
+                        };
+                    }
+
+                    let size = (html.data.len() - html.offset).min(bytes_to_read.max(0) as usize);
+                    if size > 0 {
+                        // SAFETY: CEF provides a buffer of at least |bytes_to_read| bytes.
+                        unsafe {
+                            std::ptr::copy_nonoverlapping(
+                                html.data[html.offset..].as_ptr(),
+                                data_out,
+                                size,
+                            );
+                        }
+                        html.offset += size;
+                    }
+                    if let Some(bytes_read) = bytes_read {
+                        *bytes_read = size as c_int;
+                    }
+                    (size > 0).into()
+                }
+                State::Delegate { handler, .. } => {
+                    handler.read(data_out, bytes_to_read, bytes_read, callback)
+                }
+                State::Generated { body, offset, .. } => {
+                    let size = (body.len() - *offset).min(bytes_to_read.max(0) as usize);
+                    if size > 0 {
+                        // SAFETY: CEF provides a buffer of at least |bytes_to_read| bytes.
+                        unsafe {
+                            std::ptr::copy_nonoverlapping(body[*offset..].as_ptr(), data_out, size);
+                        }
+                        *offset += size;
+                    }
+                    if let Some(bytes_read) = bytes_read {
+                        *bytes_read = size as c_int;
+                    }
+                    (size > 0).into()
+                }
+                State::Pending(_) => 0,
+            }
+        }
+
+        fn cancel(&self) {
+            let state = self.state.lock().expect("Failed to lock state");
+            if let State::Delegate { handler, .. } = &*state {
+                handler.cancel();
+            }
+        }
+    }
+}
@@ -2,9 +2,30 @@ use cef::*;
 
 pub mod app_browser_impl;
 pub mod app_renderer_impl;
+pub mod browser_state;
+pub mod certificate_policy;
 pub mod client_impl;
+pub mod crash_recovery;
+pub mod download_manager;
+pub mod dynamic_content;
+pub mod file_resource_handler;
+pub mod local_resource_handler;
+pub mod navigation_policy;
+pub mod permission_manager;
 pub mod platform;
+pub mod precompressed;
+pub mod profile;
+pub mod proxy_resource_handler;
+pub mod request_rules;
+#[cfg(all(target_os = "linux", feature = "resource-archive"))]
+pub mod resource_archive;
+pub mod resource_mount;
+pub mod resource_pipeline;
 pub mod resource_util;
+pub mod resource_watcher;
+pub mod response_filter;
+pub mod scheme;
+pub mod security_policy;
 
 use crate::{
     shared::{
@@ -72,19 +93,28 @@ pub fn run_main(main_args: &MainArgs, cmd_line: &CommandLine, sandbox_info: *mut
     let browser_process_handler = BrowserProcessHandlerImpl::new(Default::default());
     let mut app = BrowserApp::new(browser_process_handler);
 
+    let root_cache_path = profile::get_profile_root();
     let settings = Settings {
         no_sandbox: !cfg!(feature = "sandbox") as _,
+        root_cache_path: root_cache_path.to_string_lossy().as_ref().into(),
         ..Default::default()
     };
-    assert_eq!(
-        initialize(
-            Some(main_args),
-            Some(&settings),
-            Some(&mut app),
-            sandbox_info,
-        ),
-        1
-    );
+    // The root cache path makes this the only instance for the profile root. A
+    // second launch is handed to the running instance, see
+    // `on_already_running_app_relaunch`, and initialize fails here.
+    if initialize(
+        Some(main_args),
+        Some(&settings),
+        Some(&mut app),
+        sandbox_info,
+    ) != 1
+    {
+        let exit_code = get_exit_code();
+        if exit_code != sys::cef_resultcode_t::CEF_RESULT_CODE_NORMAL_EXIT_PROCESS_NOTIFIED as i32 {
+            eprintln!("failed to initialize CEF, exit code {exit_code}");
+        }
+        return;
+    }
 
     #[cfg(target_os = "macos")]
     let _delegate = crate::mac::setup_simple_app_delegate();
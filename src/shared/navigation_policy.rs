@@ -0,0 +1,375 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+/// Details about a navigation passed to `NavigationPolicy::decide`.
+#[derive(Debug, Clone)]
+pub struct NavigationInfo {
+    pub browser_id: i32,
+    pub url: String,
+    pub is_main_frame: bool,
+    pub user_gesture: bool,
+    pub is_redirect: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NavigationDecision {
+    Allow,
+    /// Cancel the navigation and open the URL with the external opener.
+    OpenExternal,
+    /// Cancel the navigation for the given reason.
+    Block(String),
+}
+
+/// Opens a URL outside of the application. Returns false if it couldn't be opened.
+pub type ExternalOpener = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Returns an opener that hands URLs to the system browser.
+pub fn default_external_opener() -> ExternalOpener {
+    Box::new(|url| {
+        let program = if cfg!(target_os = "windows") {
+            "explorer"
+        } else if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        };
+        let Ok(mut child) = Command::new(program).arg(url).spawn() else {
+            return false;
+        };
+        // Reap the process without blocking the UI thread.
+        std::thread::spawn(move || child.wait());
+        true
+    })
+}
+
+/// Controls which navigations the application browsers may perform.
+#[derive(Debug, Clone)]
+pub struct NavigationPolicy {
+    /// Origins such as `https://example.com/` that top-level navigations may go
+    /// to besides |mounted_origins|. Scheme, host and port have to match exactly,
+    /// any path is ignored. Sub frames may load other origins.
+    pub allowed_origins: Vec<String>,
+    /// The prefixes of the mount table, kept up to date by the `ClientManager`.
+    pub mounted_origins: Vec<String>,
+    /// Schemes that may never be navigated to, in any frame.
+    pub blocked_schemes: Vec<String>,
+    /// Open top-level links to other origins with the external opener instead of
+    /// blocking them. Only navigations caused by a user gesture are opened.
+    pub open_external: bool,
+    /// Schemes the external opener may be used for.
+    pub external_schemes: Vec<String>,
+    /// Block a navigation once a browser was redirected this many times within
+    /// |redirect_window|, or when a redirect returns to a URL of the chain.
+    pub max_redirects: usize,
+    pub redirect_window: Duration,
+    /// Log every decision that isn't `Allow`.
+    pub log_decisions: bool,
+}
+
+impl Default for NavigationPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            mounted_origins: Vec::new(),
+            blocked_schemes: vec!["file".to_string(), "javascript".to_string()],
+            open_external: true,
+            external_schemes: vec![
+                "http".to_string(),
+                "https".to_string(),
+                "mailto".to_string(),
+            ],
+            max_redirects: 20,
+            redirect_window: Duration::from_secs(10),
+            log_decisions: true,
+        }
+    }
+}
+
+/// Returns the lower case scheme of |url|.
+fn get_scheme(url: &str) -> String {
+    url.split_once(':')
+        .map(|(scheme, _)| scheme.to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Returns the lower case scheme, host and port of |url|. The port is `None` if
+/// it is missing or the default port of the scheme.
+pub fn get_origin(url: &str) -> Option<(String, String, Option<u16>)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let authority = rest.split(['/', '?', '#']).next()?;
+    // Drop the user info, e.g. `https://example.com@evil.net/`.
+    let authority = authority.rsplit('@').next()?;
+    let (host, port) = if let Some(ipv6) = authority.strip_prefix('[') {
+        let (host, rest) = ipv6.split_once(']')?;
+        (format!("[{host}]"), rest.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), Some(port)),
+            None => (authority.to_string(), None),
+        }
+    };
+    if host.is_empty() {
+        return None;
+    }
+
+    let port = match port.filter(|port| !port.is_empty()) {
+        Some(port) => Some(port.parse::<u16>().ok()?),
+        None => None,
+    };
+    let default_port = match scheme.as_str() {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        _ => None,
+    };
+    let port = port.filter(|port| Some(*port) != default_port);
+    Some((scheme, host.to_ascii_lowercase(), port))
+}
+
+/// Returns true if |url| has the same scheme, host and port as one of |origins|.
+pub fn has_origin<S: AsRef<str>>(url: &str, origins: &[S]) -> bool {
+    get_origin(url).is_some_and(|origin| {
+        origins
+            .iter()
+            .any(|candidate| get_origin(candidate.as_ref()).as_ref() == Some(&origin))
+    })
+}
+
+impl NavigationPolicy {
+    pub fn decide(
+        &self,
+        info: &NavigationInfo,
+        redirects: &mut RedirectTracker,
+    ) -> NavigationDecision {
+        let scheme = get_scheme(&info.url);
+        if self
+            .blocked_schemes
+            .iter()
+            .any(|blocked| blocked.eq_ignore_ascii_case(&scheme))
+        {
+            return NavigationDecision::Block(format!("the {scheme}: scheme is blocked"));
+        }
+
+        if !info.is_main_frame {
+            return NavigationDecision::Allow;
+        }
+
+        if let Some(reason) = redirects.record(info, self) {
+            return NavigationDecision::Block(reason);
+        }
+
+        if info.url == "about:blank"
+            || has_origin(&info.url, &self.allowed_origins)
+            || has_origin(&info.url, &self.mounted_origins)
+        {
+            return NavigationDecision::Allow;
+        }
+
+        let external = self
+            .external_schemes
+            .iter()
+            .any(|external| external.eq_ignore_ascii_case(&scheme));
+        if self.open_external && external && info.user_gesture {
+            NavigationDecision::OpenExternal
+        } else {
+            NavigationDecision::Block("the origin is not allowed".to_string())
+        }
+    }
+}
+
+/// Keeps the current redirect chain of each browser.
+#[derive(Debug, Default)]
+pub struct RedirectTracker {
+    chains: HashMap<i32, VecDeque<(Instant, String)>>,
+}
+
+impl RedirectTracker {
+    /// Records a top-level navigation. Returns the reason if it looks like a
+    /// redirect loop.
+    fn record(&mut self, info: &NavigationInfo, policy: &NavigationPolicy) -> Option<String> {
+        self.record_at(info, Instant::now(), policy)
+    }
+
+    fn record_at(
+        &mut self,
+        info: &NavigationInfo,
+        now: Instant,
+        policy: &NavigationPolicy,
+    ) -> Option<String> {
+        let chain = self.chains.entry(info.browser_id).or_default();
+        if !info.is_redirect {
+            // A new navigation starts a new chain.
+            chain.clear();
+            chain.push_back((now, info.url.clone()));
+            return None;
+        }
+
+        while chain
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > policy.redirect_window)
+        {
+            chain.pop_front();
+        }
+
+        let reason = if chain.iter().any(|(_, url)| *url == info.url) {
+            Some(format!("redirect loop back to {}", info.url))
+        } else if chain.len() >= policy.max_redirects {
+            Some(format!("more than {} redirects", policy.max_redirects))
+        } else {
+            None
+        };
+        chain.push_back((now, info.url.clone()));
+        reason
+    }
+
+    pub fn remove(&mut self, browser_id: i32) {
+        self.chains.remove(&browser_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn navigation(url: &str) -> NavigationInfo {
+        NavigationInfo {
+            browser_id: 1,
+            url: url.to_string(),
+            is_main_frame: true,
+            user_gesture: true,
+            is_redirect: false,
+        }
+    }
+
+    fn redirect(url: &str) -> NavigationInfo {
+        NavigationInfo {
+            is_redirect: true,
+            ..navigation(url)
+        }
+    }
+
+    #[test]
+    fn origins() {
+        assert_eq!(
+            get_origin("https://Example.com:443/a?b#c"),
+            Some(("https".to_string(), "example.com".to_string(), None))
+        );
+        assert_eq!(
+            get_origin("http://user:pw@localhost:8080"),
+            Some(("http".to_string(), "localhost".to_string(), Some(8080)))
+        );
+        assert_eq!(
+            get_origin("http://[::1]:3000/"),
+            Some(("http".to_string(), "[::1]".to_string(), Some(3000)))
+        );
+        assert_eq!(get_origin("about:blank"), None);
+        assert!(has_origin(
+            "https://example.com/x",
+            &["https://EXAMPLE.com"]
+        ));
+        assert!(!has_origin(
+            "https://example.com.evil.net/",
+            &["https://example.com"]
+        ));
+        assert_eq!(get_origin("https://example.com:99999/"), None);
+    }
+
+    #[test]
+    fn decide() {
+        let policy = NavigationPolicy {
+            allowed_origins: vec!["app://main/".to_string()],
+            mounted_origins: vec!["https://example.com/assets/".to_string()],
+            ..Default::default()
+        };
+        let mut redirects = RedirectTracker::default();
+        let mut decide = |info: NavigationInfo| policy.decide(&info, &mut redirects);
+
+        assert_eq!(
+            decide(navigation("https://example.com/page.html")),
+            NavigationDecision::Allow
+        );
+        assert_eq!(
+            decide(navigation("HTTPS://EXAMPLE.COM:443/")),
+            NavigationDecision::Allow
+        );
+        assert_eq!(
+            decide(navigation("app://main/index.html")),
+            NavigationDecision::Allow
+        );
+        assert_eq!(decide(navigation("about:blank")), NavigationDecision::Allow);
+
+        // Lookalike origins aren't allowed.
+        for url in [
+            "https://example.com.evil.net/",
+            "https://example.com@evil.net/",
+            "https://example.com:8443/",
+            "http://example.com/",
+        ] {
+            assert_eq!(
+                decide(navigation(url)),
+                NavigationDecision::OpenExternal,
+                "{url}"
+            );
+        }
+        assert_eq!(
+            decide(NavigationInfo {
+                user_gesture: false,
+                ..navigation("https://other.com/")
+            }),
+            NavigationDecision::Block("the origin is not allowed".to_string())
+        );
+        for url in ["ftp://other.com/", "app://main.evil/"] {
+            assert!(
+                matches!(decide(navigation(url)), NavigationDecision::Block(_)),
+                "{url}"
+            );
+        }
+
+        // Blocked schemes are blocked in sub frames too.
+        assert!(matches!(
+            decide(NavigationInfo {
+                is_main_frame: false,
+                ..navigation("file:///etc/passwd")
+            }),
+            NavigationDecision::Block(_)
+        ));
+        assert_eq!(
+            decide(NavigationInfo {
+                is_main_frame: false,
+                ..navigation("https://other.com/")
+            }),
+            NavigationDecision::Allow
+        );
+    }
+
+    #[test]
+    fn redirect_loops() {
+        let policy = NavigationPolicy {
+            max_redirects: 3,
+            ..Default::default()
+        };
+        let mut redirects = RedirectTracker::default();
+        let start = Instant::now();
+        let mut record = |info: NavigationInfo, secs| {
+            redirects.record_at(&info, start + Duration::from_secs(secs), &policy)
+        };
+
+        assert_eq!(record(navigation("https://example.com/a"), 0), None);
+        assert_eq!(record(redirect("https://example.com/b"), 0), None);
+        assert!(record(redirect("https://example.com/a"), 0).is_some());
+
+        // A new navigation starts a new chain.
+        assert_eq!(record(navigation("https://example.com/a"), 1), None);
+        assert_eq!(record(redirect("https://example.com/b"), 1), None);
+        assert_eq!(record(redirect("https://example.com/c"), 1), None);
+        assert!(record(redirect("https://example.com/d"), 1).is_some());
+
+        // Redirects outside of the window are forgotten.
+        assert_eq!(record(navigation("https://example.com/a"), 100), None);
+        assert_eq!(record(redirect("https://example.com/b"), 100), None);
+        assert_eq!(record(redirect("https://example.com/a"), 200), None);
+    }
+}
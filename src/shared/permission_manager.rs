@@ -0,0 +1,998 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use cef::{
+    sys::{
+        cef_media_access_permission_types_t as MediaPermission,
+        cef_permission_request_types_t as PromptPermission,
+    },
+    wrapper::message_router::{BrowserSideCallback, BrowserSideHandler},
+    *,
+};
+
+use crate::shared::{
+    navigation_policy::has_origin, profile::get_profiles_dir, request_rules::matches_pattern,
+    resource_util::TEST_ORIGIN, scheme::APP_ORIGIN,
+};
+
+/// Persistent query that receives the permission requests the page should ask
+/// the user about.
+pub const PERMISSION_REQUESTS_MESSAGE: &str = "PermissionRequests";
+/// Answer to a permission request: `PermissionResponse:<id>:allow|deny[:remember]`.
+const PERMISSION_RESPONSE_PREFIX: &str = "PermissionResponse:";
+/// Name of the file the decisions of a profile are stored in.
+const STORE_FILE_NAME: &str = "permissions.tsv";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PermissionType {
+    Camera,
+    Microphone,
+    ScreenCapture,
+    Geolocation,
+    Notifications,
+    Clipboard,
+    /// Any other permission, e.g. MIDI or window management.
+    Other,
+}
+
+const PROMPT_PERMISSIONS: [(PromptPermission, PermissionType); 5] = [
+    (
+        PromptPermission::CEF_PERMISSION_TYPE_CAMERA_STREAM,
+        PermissionType::Camera,
+    ),
+    (
+        PromptPermission::CEF_PERMISSION_TYPE_MIC_STREAM,
+        PermissionType::Microphone,
+    ),
+    (
+        PromptPermission::CEF_PERMISSION_TYPE_GEOLOCATION,
+        PermissionType::Geolocation,
+    ),
+    (
+        PromptPermission::CEF_PERMISSION_TYPE_NOTIFICATIONS,
+        PermissionType::Notifications,
+    ),
+    (
+        PromptPermission::CEF_PERMISSION_TYPE_CLIPBOARD,
+        PermissionType::Clipboard,
+    ),
+];
+
+const MEDIA_PERMISSIONS: [(MediaPermission, PermissionType); 4] = [
+    (
+        MediaPermission::CEF_MEDIA_PERMISSION_DEVICE_AUDIO_CAPTURE,
+        PermissionType::Microphone,
+    ),
+    (
+        MediaPermission::CEF_MEDIA_PERMISSION_DEVICE_VIDEO_CAPTURE,
+        PermissionType::Camera,
+    ),
+    (
+        MediaPermission::CEF_MEDIA_PERMISSION_DESKTOP_AUDIO_CAPTURE,
+        PermissionType::ScreenCapture,
+    ),
+    (
+        MediaPermission::CEF_MEDIA_PERMISSION_DESKTOP_VIDEO_CAPTURE,
+        PermissionType::ScreenCapture,
+    ),
+];
+
+impl PermissionType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Camera => "camera",
+            Self::Microphone => "microphone",
+            Self::ScreenCapture => "screen_capture",
+            Self::Geolocation => "geolocation",
+            Self::Notifications => "notifications",
+            Self::Clipboard => "clipboard",
+            Self::Other => "other",
+        }
+    }
+
+    fn parse(permission: &str) -> Option<Self> {
+        [
+            Self::Camera,
+            Self::Microphone,
+            Self::ScreenCapture,
+            Self::Geolocation,
+            Self::Notifications,
+            Self::Clipboard,
+            Self::Other,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str() == permission)
+    }
+
+    /// Returns the types of the `cef_permission_request_types_t` bits in
+    /// |permissions|.
+    fn from_prompt_permissions(permissions: u32) -> Vec<Self> {
+        let mut known = 0;
+        let mut types = Vec::new();
+        for (bit, permission) in PROMPT_PERMISSIONS {
+            known |= bit as u32;
+            if permissions & bit as u32 != 0 {
+                types.push(permission);
+            }
+        }
+        if permissions & !known != 0 {
+            types.push(Self::Other);
+        }
+        types
+    }
+
+    /// Returns the types of the `cef_media_access_permission_types_t` bits in
+    /// |permissions|.
+    fn from_media_permissions(permissions: u32) -> Vec<Self> {
+        let mut types = Vec::new();
+        for (bit, permission) in MEDIA_PERMISSIONS {
+            if permissions & bit as u32 != 0 && !types.contains(&permission) {
+                types.push(permission);
+            }
+        }
+        types
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDecision {
+    Allow,
+    Deny,
+    /// Forward the request to the page UI subscribed with
+    /// `PERMISSION_REQUESTS_MESSAGE`. Denied if no page is subscribed.
+    Ask,
+}
+
+#[derive(Debug, Clone)]
+pub struct PermissionRule {
+    /// Origin pattern without trailing slash, see `matches_pattern`, e.g.
+    /// `https://*.example.com`.
+    pub origin: String,
+    /// The permission the rule applies to, all if `None`.
+    pub permission: Option<PermissionType>,
+    pub decision: PermissionDecision,
+}
+
+/// Decides the permission requests of pages by origin and permission type.
+#[derive(Debug, Clone)]
+pub struct PermissionPolicy {
+    /// The first matching rule decides.
+    pub rules: Vec<PermissionRule>,
+    /// Decision for requests no rule matches.
+    pub default: PermissionDecision,
+    /// Origins of the pages that may answer forwarded requests.
+    pub ui_origins: Vec<String>,
+    /// Directory the decisions the user asked to remember are stored in, in
+    /// `<profile>/permissions.tsv` per profile. They are only kept for the
+    /// session if `None`.
+    pub store_dir: Option<PathBuf>,
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                PermissionRule {
+                    origin: normalize_origin(TEST_ORIGIN),
+                    permission: None,
+                    decision: PermissionDecision::Ask,
+                },
+                PermissionRule {
+                    origin: normalize_origin(APP_ORIGIN),
+                    permission: None,
+                    decision: PermissionDecision::Ask,
+                },
+            ],
+            default: PermissionDecision::Deny,
+            ui_origins: vec![TEST_ORIGIN.to_string(), APP_ORIGIN.to_string()],
+            store_dir: Some(get_profiles_dir()),
+        }
+    }
+}
+
+impl PermissionPolicy {
+    /// Adds a rule in front of the existing ones.
+    pub fn with_rule(
+        mut self,
+        origin: &str,
+        permission: Option<PermissionType>,
+        decision: PermissionDecision,
+    ) -> Self {
+        self.rules.insert(
+            0,
+            PermissionRule {
+                origin: normalize_origin(origin),
+                permission,
+                decision,
+            },
+        );
+        self
+    }
+
+    pub fn with_default(mut self, decision: PermissionDecision) -> Self {
+        self.default = decision;
+        self
+    }
+
+    pub fn decide(&self, origin: &str, permission: PermissionType) -> PermissionDecision {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.permission
+                    .is_none_or(|candidate| candidate == permission)
+                    && matches_pattern(&rule.origin, origin)
+            })
+            .map_or(self.default, |rule| rule.decision)
+    }
+}
+
+/// Returns |origin| without the trailing slash.
+fn normalize_origin(origin: &str) -> String {
+    origin.trim_end_matches('/').to_string()
+}
+
+/// Returns the decisions of the store file |contents|, skipping invalid lines.
+fn parse_store(contents: &str) -> HashMap<(String, PermissionType), bool> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let (origin, permission, decision) = (fields.next()?, fields.next()?, fields.next()?);
+            let allowed = match decision {
+                "allow" => true,
+                "deny" => false,
+                _ => return None,
+            };
+            Some((
+                (origin.to_string(), PermissionType::parse(permission)?),
+                allowed,
+            ))
+        })
+        .collect()
+}
+
+enum PermissionCallback {
+    Media {
+        callback: MediaAccessCallback,
+        requested_permissions: u32,
+    },
+    Prompt {
+        prompt_id: u64,
+        callback: PermissionPromptCallback,
+    },
+    /// Records the allowed types instead of answering CEF.
+    #[cfg(test)]
+    Recorded(Arc<Mutex<Option<Vec<PermissionType>>>>),
+}
+
+impl PermissionCallback {
+    /// Grants the |allowed| types of the |requested| ones.
+    fn complete(self, requested: &[PermissionType], allowed: &[PermissionType]) {
+        match self {
+            Self::Media {
+                callback,
+                requested_permissions,
+            } => {
+                let permissions = MEDIA_PERMISSIONS
+                    .iter()
+                    .filter(|(_, permission)| allowed.contains(permission))
+                    .fold(0, |permissions, (bit, _)| permissions | *bit as u32)
+                    & requested_permissions;
+                if permissions == 0 {
+                    callback.cancel();
+                } else {
+                    callback.cont(permissions);
+                }
+            }
+            // Prompts can only be accepted as a whole.
+            Self::Prompt { callback, .. } => {
+                if requested
+                    .iter()
+                    .all(|permission| allowed.contains(permission))
+                {
+                    callback.cont(PermissionRequestResult::ACCEPT);
+                } else {
+                    callback.cont(PermissionRequestResult::DENY);
+                }
+            }
+            #[cfg(test)]
+            Self::Recorded(result) => {
+                *result.lock().expect("Failed to lock result") = Some(allowed.to_vec());
+            }
+        }
+    }
+}
+
+struct PendingRequest {
+    browser_id: i32,
+    profile: String,
+    origin: String,
+    requested: Vec<PermissionType>,
+    /// Types the policy allowed without asking.
+    allowed: Vec<PermissionType>,
+    /// Types the page UI is asked about.
+    ask: Vec<PermissionType>,
+    callback: PermissionCallback,
+}
+
+struct Subscription {
+    browser_id: i32,
+    query_id: i64,
+    callback: Arc<Mutex<dyn BrowserSideCallback>>,
+}
+
+/// Decides permission requests with a `PermissionPolicy`, forwarding them to the
+/// page UI where the policy says so, and remembers the answers of the user.
+pub struct PermissionManager {
+    policy: PermissionPolicy,
+    /// Decisions the user asked to remember, by profile, origin and type.
+    /// Profiles are loaded from their store file on first use.
+    stored: HashMap<String, HashMap<(String, PermissionType), bool>>,
+    pending: HashMap<u64, PendingRequest>,
+    next_request_id: u64,
+    subscriptions: Vec<Subscription>,
+}
+
+impl PermissionManager {
+    pub fn new(policy: PermissionPolicy) -> Self {
+        Self {
+            policy,
+            stored: HashMap::new(),
+            pending: HashMap::new(),
+            next_request_id: 1,
+            subscriptions: Vec::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &PermissionPolicy {
+        &self.policy
+    }
+
+    /// Replaces the policy. The decisions stored by it are loaded on first use.
+    pub fn set_policy(&mut self, policy: PermissionPolicy) {
+        self.policy = policy;
+        self.stored.clear();
+    }
+
+    /// Returns the decision for |permission| of |origin| in |profile|,
+    /// preferring the stored answers of the user over the policy.
+    pub fn decide(
+        &mut self,
+        profile: &str,
+        origin: &str,
+        permission: PermissionType,
+    ) -> PermissionDecision {
+        let origin = normalize_origin(origin);
+        match self
+            .stored(profile)
+            .get(&(origin.clone(), permission))
+            .copied()
+        {
+            Some(true) => PermissionDecision::Allow,
+            Some(false) => PermissionDecision::Deny,
+            None => self.policy.decide(&origin, permission),
+        }
+    }
+
+    /// Returns the decisions stored for |profile| as
+    /// `(origin, permission, allowed)`.
+    pub fn stored_decisions(&mut self, profile: &str) -> Vec<(String, PermissionType, bool)> {
+        let mut decisions = self
+            .stored(profile)
+            .iter()
+            .map(|((origin, permission), allowed)| (origin.clone(), *permission, *allowed))
+            .collect::<Vec<_>>();
+        decisions.sort_by(|a, b| (&a.0, a.1.as_str()).cmp(&(&b.0, b.1.as_str())));
+        decisions
+    }
+
+    /// Stores the decision for |permission| of |origin| in |profile|, or
+    /// forgets it if |allowed| is `None`.
+    pub fn set_stored_decision(
+        &mut self,
+        profile: &str,
+        origin: &str,
+        permission: PermissionType,
+        allowed: Option<bool>,
+    ) {
+        let key = (normalize_origin(origin), permission);
+        let stored = self.stored(profile);
+        match allowed {
+            Some(allowed) => stored.insert(key, allowed),
+            None => stored.remove(&key),
+        };
+        self.save(profile);
+    }
+
+    /// Returns the file the decisions of |profile| are stored in.
+    fn store_file(&self, profile: &str) -> Option<PathBuf> {
+        let store_dir = self.policy.store_dir.as_ref()?;
+        Some(store_dir.join(profile).join(STORE_FILE_NAME))
+    }
+
+    /// Returns the stored decisions of |profile|, loading them on first use.
+    fn stored(&mut self, profile: &str) -> &mut HashMap<(String, PermissionType), bool> {
+        if !self.stored.contains_key(profile) {
+            let stored = self
+                .store_file(profile)
+                .and_then(|store_file| fs::read_to_string(store_file).ok())
+                .map(|store| parse_store(&store))
+                .unwrap_or_default();
+            self.stored.insert(profile.to_string(), stored);
+        }
+        self.stored
+            .get_mut(profile)
+            .expect("profile was just loaded")
+    }
+
+    fn save(&mut self, profile: &str) {
+        let Some(store_file) = self.store_file(profile) else {
+            return;
+        };
+        let store = self
+            .stored_decisions(profile)
+            .into_iter()
+            .map(|(origin, permission, allowed)| {
+                let decision = if allowed { "allow" } else { "deny" };
+                format!("{origin}\t{}\t{decision}\n", permission.as_str())
+            })
+            .collect::<String>();
+        if let Some(parent) = store_file.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(err) = fs::write(&store_file, store) {
+            eprintln!(
+                "failed to store the permissions in {}: {err}",
+                store_file.display()
+            );
+        }
+    }
+
+    // CefPermissionHandler method
+    pub fn on_request_media_access_permission(
+        &mut self,
+        browser_id: i32,
+        profile: &str,
+        origin: &str,
+        requested_permissions: u32,
+        callback: MediaAccessCallback,
+    ) {
+        let requested = PermissionType::from_media_permissions(requested_permissions);
+        self.request(
+            browser_id,
+            profile,
+            origin,
+            requested,
+            PermissionCallback::Media {
+                callback,
+                requested_permissions,
+            },
+        );
+    }
+
+    // CefPermissionHandler method
+    pub fn on_show_permission_prompt(
+        &mut self,
+        browser_id: i32,
+        profile: &str,
+        prompt_id: u64,
+        origin: &str,
+        requested_permissions: u32,
+        callback: PermissionPromptCallback,
+    ) {
+        let requested = PermissionType::from_prompt_permissions(requested_permissions);
+        self.request(
+            browser_id,
+            profile,
+            origin,
+            requested,
+            PermissionCallback::Prompt {
+                prompt_id,
+                callback,
+            },
+        );
+    }
+
+    // CefPermissionHandler method
+    pub fn on_dismiss_permission_prompt(&mut self, prompt_id: u64) {
+        let id = self
+            .pending
+            .iter()
+            .find_map(|(id, pending)| match pending.callback {
+                PermissionCallback::Prompt {
+                    prompt_id: candidate,
+                    ..
+                } if candidate == prompt_id => Some(*id),
+                _ => None,
+            });
+        // CEF closed the prompt itself, e.g. because the page navigated away.
+        if let Some(id) = id
+            && let Some(pending) = self.pending.remove(&id)
+        {
+            self.send_event(
+                pending.browser_id,
+                &format!(r#"{{"id":{id},"dismissed":true}}"#),
+            );
+        }
+    }
+
+    fn request(
+        &mut self,
+        browser_id: i32,
+        profile: &str,
+        origin: &str,
+        requested: Vec<PermissionType>,
+        callback: PermissionCallback,
+    ) {
+        let origin = normalize_origin(origin);
+        let decisions = requested
+            .iter()
+            .map(|permission| (*permission, self.decide(profile, &origin, *permission)))
+            .collect::<Vec<_>>();
+        let with_decision = |decision| {
+            decisions
+                .iter()
+                .filter(|(_, candidate)| *candidate == decision)
+                .map(|(permission, _)| *permission)
+                .collect::<Vec<_>>()
+        };
+        let allowed = with_decision(PermissionDecision::Allow);
+        let ask = with_decision(PermissionDecision::Ask);
+
+        if !ask.is_empty() && self.has_subscription(browser_id) {
+            let id = self.next_request_id;
+            self.next_request_id += 1;
+            let permissions = ask
+                .iter()
+                .map(|permission| format!(r#""{}""#, permission.as_str()))
+                .collect::<Vec<_>>()
+                .join(",");
+            self.send_event(
+                browser_id,
+                &format!(
+                    r#"{{"id":{id},"origin":"{}","permissions":[{permissions}]}}"#,
+                    origin.replace('\\', "\\\\").replace('"', "\\\"")
+                ),
+            );
+            self.pending.insert(
+                id,
+                PendingRequest {
+                    browser_id,
+                    profile: profile.to_string(),
+                    origin,
+                    requested,
+                    allowed,
+                    ask,
+                    callback,
+                },
+            );
+            return;
+        }
+
+        if !ask.is_empty() {
+            eprintln!("no page to ask about the permissions of {origin}, denying {ask:?}");
+        }
+        callback.complete(&requested, &allowed);
+    }
+
+    /// Completes the request |id| of |browser_id| with the answer of the user.
+    /// Returns false if there is no such request.
+    pub fn respond(&mut self, browser_id: i32, id: u64, allow: bool, remember: bool) -> bool {
+        if !self
+            .pending
+            .get(&id)
+            .is_some_and(|pending| pending.browser_id == browser_id)
+        {
+            return false;
+        }
+        let mut pending = self.pending.remove(&id).expect("request was just found");
+
+        if remember {
+            let stored = self.stored(&pending.profile);
+            for permission in &pending.ask {
+                stored.insert((pending.origin.clone(), *permission), allow);
+            }
+            self.save(&pending.profile);
+        }
+        if allow {
+            pending.allowed.append(&mut pending.ask);
+        }
+        pending
+            .callback
+            .complete(&pending.requested, &pending.allowed);
+        true
+    }
+
+    fn has_subscription(&self, browser_id: i32) -> bool {
+        self.subscriptions
+            .iter()
+            .any(|subscription| subscription.browser_id == browser_id)
+    }
+
+    /// Sends the permission requests of |browser_id| to |callback| until the
+    /// query is canceled.
+    pub fn subscribe(
+        &mut self,
+        browser_id: i32,
+        query_id: i64,
+        callback: Arc<Mutex<dyn BrowserSideCallback>>,
+    ) {
+        self.subscriptions.push(Subscription {
+            browser_id,
+            query_id,
+            callback,
+        });
+    }
+
+    pub fn unsubscribe(&mut self, query_id: i64) {
+        let browser_ids = self
+            .subscriptions
+            .iter()
+            .filter(|subscription| subscription.query_id == query_id)
+            .map(|subscription| subscription.browser_id)
+            .collect::<Vec<_>>();
+        self.subscriptions
+            .retain(|subscription| subscription.query_id != query_id);
+        for browser_id in browser_ids {
+            self.deny_unanswerable(browser_id);
+        }
+    }
+
+    /// Drops the subscriptions of a closing browser.
+    pub fn remove_browser(&mut self, browser_id: i32) {
+        self.subscriptions
+            .retain(|subscription| subscription.browser_id != browser_id);
+        self.deny_unanswerable(browser_id);
+    }
+
+    /// Denies the asked permissions of |browser_id| once no page can answer them.
+    fn deny_unanswerable(&mut self, browser_id: i32) {
+        if self.has_subscription(browser_id) {
+            return;
+        }
+        let ids = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.browser_id == browser_id)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            if let Some(pending) = self.pending.remove(&id) {
+                pending
+                    .callback
+                    .complete(&pending.requested, &pending.allowed);
+            }
+        }
+    }
+
+    fn send_event(&self, browser_id: i32, event: &str) {
+        for subscription in self
+            .subscriptions
+            .iter()
+            .filter(|subscription| subscription.browser_id == browser_id)
+        {
+            subscription
+                .callback
+                .lock()
+                .expect("Failed to lock callback")
+                .success_str(event);
+        }
+    }
+}
+
+/// Answers the permission queries of the main frame of the UI origins.
+pub struct PermissionMessageHandler {
+    permissions: Arc<Mutex<PermissionManager>>,
+}
+
+impl PermissionMessageHandler {
+    pub fn new(permissions: Arc<Mutex<PermissionManager>>) -> Self {
+        Self { permissions }
+    }
+}
+
+impl std::fmt::Debug for PermissionMessageHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PermissionMessageHandler")
+            .finish_non_exhaustive()
+    }
+}
+
+impl BrowserSideHandler for PermissionMessageHandler {
+    fn on_query_str(
+        &self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        query_id: i64,
+        request: &str,
+        persistent: bool,
+        callback: Arc<Mutex<dyn BrowserSideCallback>>,
+    ) -> bool {
+        if request != PERMISSION_REQUESTS_MESSAGE
+            && !request.starts_with(PERMISSION_RESPONSE_PREFIX)
+        {
+            return false;
+        }
+        let Some(browser_id) = browser.map(|browser| browser.identifier()) else {
+            return false;
+        };
+        let mut permissions = self.permissions.lock().expect("Failed to lock permissions");
+
+        // Pages must not be able to grant their own permissions.
+        let is_ui_frame = frame.is_some_and(|frame| {
+            let url = CefString::from(&frame.url()).to_string();
+            frame.is_main() != 0 && has_origin(&url, &permissions.policy().ui_origins)
+        });
+        let mut callback_lock = callback.lock().expect("Failed to lock callback");
+        if !is_ui_frame {
+            callback_lock.failure(0, "permission queries are limited to the UI origins");
+            return true;
+        }
+
+        if request == PERMISSION_REQUESTS_MESSAGE {
+            if persistent {
+                drop(callback_lock);
+                permissions.subscribe(browser_id, query_id, callback);
+            } else {
+                callback_lock.failure(0, "PermissionRequests must be a persistent query");
+            }
+            return true;
+        }
+
+        let mut fields = request[PERMISSION_RESPONSE_PREFIX.len()..].split(':');
+        let id = fields.next().and_then(|id| id.parse().ok());
+        let allow = match fields.next() {
+            Some("allow") => Some(true),
+            Some("deny") => Some(false),
+            _ => None,
+        };
+        let remember = fields.next() == Some("remember");
+        match (id, allow) {
+            (Some(id), Some(allow)) if permissions.respond(browser_id, id, allow, remember) => {
+                callback_lock.success_str("");
+            }
+            _ => callback_lock.failure(0, &format!("invalid permission response {request}")),
+        }
+        true
+    }
+
+    fn on_query_canceled(&self, _browser: Option<Browser>, _frame: Option<Frame>, query_id: i64) {
+        self.permissions
+            .lock()
+            .expect("Failed to lock permissions")
+            .unsubscribe(query_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::profile::DEFAULT_PROFILE;
+
+    fn policy() -> PermissionPolicy {
+        PermissionPolicy {
+            rules: Vec::new(),
+            default: PermissionDecision::Deny,
+            ui_origins: Vec::new(),
+            store_dir: None,
+        }
+    }
+
+    /// Adds a pending request of |browser_id| asking about |ask|, returning its
+    /// id and the result recorded once it completes.
+    fn add_request(
+        manager: &mut PermissionManager,
+        browser_id: i32,
+        requested: &[PermissionType],
+        ask: &[PermissionType],
+    ) -> (u64, Arc<Mutex<Option<Vec<PermissionType>>>>) {
+        let result = Arc::new(Mutex::new(None));
+        let id = manager.next_request_id;
+        manager.next_request_id += 1;
+        manager.pending.insert(
+            id,
+            PendingRequest {
+                browser_id,
+                profile: "work".to_string(),
+                origin: "https://example.com".to_string(),
+                requested: requested.to_vec(),
+                allowed: requested
+                    .iter()
+                    .filter(|permission| !ask.contains(permission))
+                    .copied()
+                    .collect(),
+                ask: ask.to_vec(),
+                callback: PermissionCallback::Recorded(result.clone()),
+            },
+        );
+        (id, result)
+    }
+
+    fn result(result: &Arc<Mutex<Option<Vec<PermissionType>>>>) -> Option<Vec<PermissionType>> {
+        result.lock().expect("Failed to lock result").clone()
+    }
+
+    #[test]
+    fn policy_rule_order() {
+        let policy = policy()
+            .with_rule("https://*.example.com", None, PermissionDecision::Ask)
+            .with_rule(
+                "https://meet.example.com/",
+                Some(PermissionType::Camera),
+                PermissionDecision::Allow,
+            );
+
+        assert_eq!(
+            policy.decide("https://meet.example.com", PermissionType::Camera),
+            PermissionDecision::Allow
+        );
+        assert_eq!(
+            policy.decide("https://meet.example.com", PermissionType::Microphone),
+            PermissionDecision::Ask
+        );
+        assert_eq!(
+            policy.decide("https://docs.example.com", PermissionType::Camera),
+            PermissionDecision::Ask
+        );
+        assert_eq!(
+            policy.decide("https://example.org", PermissionType::Camera),
+            PermissionDecision::Deny
+        );
+        assert_eq!(
+            policy
+                .with_default(PermissionDecision::Allow)
+                .decide("https://example.org", PermissionType::Camera),
+            PermissionDecision::Allow
+        );
+    }
+
+    #[test]
+    fn respond_without_remember() {
+        let mut manager = PermissionManager::new(policy());
+        let (id, allowed) = add_request(
+            &mut manager,
+            1,
+            &[PermissionType::Camera, PermissionType::Microphone],
+            &[PermissionType::Camera],
+        );
+
+        assert!(!manager.respond(2, id, true, false));
+        assert!(manager.respond(1, id, true, false));
+        assert_eq!(
+            result(&allowed),
+            Some(vec![PermissionType::Microphone, PermissionType::Camera])
+        );
+        assert!(!manager.respond(1, id, true, false));
+        assert!(manager.stored_decisions("work").is_empty());
+    }
+
+    #[test]
+    fn respond_with_remember() {
+        let mut manager = PermissionManager::new(policy());
+        let (id, allowed) = add_request(
+            &mut manager,
+            1,
+            &[PermissionType::Camera, PermissionType::Microphone],
+            &[PermissionType::Camera],
+        );
+
+        assert!(manager.respond(1, id, false, true));
+        assert_eq!(result(&allowed), Some(vec![PermissionType::Microphone]));
+        assert_eq!(
+            manager.stored_decisions("work"),
+            vec![(
+                "https://example.com".to_string(),
+                PermissionType::Camera,
+                false
+            )]
+        );
+        assert_eq!(
+            manager.decide("work", "https://example.com/", PermissionType::Camera),
+            PermissionDecision::Deny
+        );
+        assert!(manager.stored_decisions(DEFAULT_PROFILE).is_empty());
+    }
+
+    #[test]
+    fn deny_unanswerable() {
+        let mut manager = PermissionManager::new(policy());
+        let (first, first_allowed) = add_request(
+            &mut manager,
+            1,
+            &[PermissionType::Camera, PermissionType::Geolocation],
+            &[PermissionType::Camera],
+        );
+        let (second, second_allowed) = add_request(
+            &mut manager,
+            2,
+            &[PermissionType::Camera],
+            &[PermissionType::Camera],
+        );
+
+        manager.remove_browser(1);
+        assert_eq!(
+            result(&first_allowed),
+            Some(vec![PermissionType::Geolocation])
+        );
+        assert_eq!(result(&second_allowed), None);
+        assert!(!manager.respond(1, first, true, false));
+        assert!(manager.respond(2, second, true, false));
+    }
+
+    #[test]
+    fn store_round_trip() {
+        let store_dir =
+            std::env::temp_dir().join(format!("permission_store_{}", std::process::id()));
+        let policy = PermissionPolicy {
+            store_dir: Some(store_dir.clone()),
+            ..policy()
+        };
+
+        let mut manager = PermissionManager::new(policy.clone());
+        manager.set_stored_decision(
+            "work",
+            "https://example.com/",
+            PermissionType::Camera,
+            Some(true),
+        );
+        manager.set_stored_decision(
+            "work",
+            "https://example.com",
+            PermissionType::Geolocation,
+            Some(false),
+        );
+        manager.set_stored_decision(
+            "work",
+            "https://example.org",
+            PermissionType::Clipboard,
+            Some(true),
+        );
+        manager.set_stored_decision(
+            "work",
+            "https://example.org",
+            PermissionType::Clipboard,
+            None,
+        );
+
+        let mut manager = PermissionManager::new(policy);
+        assert_eq!(
+            manager.stored_decisions("work"),
+            vec![
+                (
+                    "https://example.com".to_string(),
+                    PermissionType::Camera,
+                    true
+                ),
+                (
+                    "https://example.com".to_string(),
+                    PermissionType::Geolocation,
+                    false
+                ),
+            ]
+        );
+        assert!(manager.stored_decisions("home").is_empty());
+        assert!(store_dir.join("work").join(STORE_FILE_NAME).is_file());
+        assert!(!store_dir.join("home").exists());
+
+        let _ = fs::remove_dir_all(store_dir);
+    }
+
+    #[test]
+    fn store_skips_invalid_lines() {
+        let stored = parse_store(concat!(
+            "https://a.com\tcamera\tallow\n",
+            "https://b.com\tunknown\tallow\n",
+            "https://c.com\tcamera\tmaybe\n",
+            "garbage\n",
+        ));
+        assert_eq!(stored.len(), 1);
+        assert_eq!(
+            stored.get(&("https://a.com".to_string(), PermissionType::Camera)),
+            Some(&true)
+        );
+    }
+}
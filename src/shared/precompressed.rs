@@ -0,0 +1,397 @@
+use std::{
+    io,
+    os::raw::c_int,
+    sync::{Arc, Mutex},
+};
+
+use cef::{wrapper::resource_manager::get_mime_type, *};
+
+use crate::shared::resource_mount::Mount;
+
+/// Encodings of precompressed variants in order of preference, with the suffix of
+/// the variant's file name.
+pub const PRECOMPRESSED_ENCODINGS: &[(&str, &str)] = &[("br", ".br"), ("gzip", ".gz")];
+
+// Shared with build.rs.
+include!("precompressed_extensions.rs");
+
+/// Returns true if |resource_path| may have precompressed variants. Variants are
+/// only served with the `precompress` feature.
+pub fn has_precompressed_variants(resource_path: &str) -> bool {
+    cfg!(feature = "precompress")
+        && resource_path
+            .rsplit_once('.')
+            .is_some_and(|(_, extension)| {
+                PRECOMPRESSED_EXTENSIONS
+                    .iter()
+                    .any(|candidate| extension.eq_ignore_ascii_case(candidate))
+            })
+}
+
+/// Returns the encodings of `PRECOMPRESSED_ENCODINGS` that the `Accept-Encoding`
+/// header value |accept_encoding| allows, best first.
+pub fn accepted_encodings(accept_encoding: &str) -> Vec<&'static str> {
+    let mut accepted = Vec::new();
+    for (encoding, _) in PRECOMPRESSED_ENCODINGS {
+        let quality = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let name = params.next()?;
+                if !name.eq_ignore_ascii_case(encoding) && name != "*" {
+                    return None;
+                }
+                let quality = params
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |quality| quality.parse::<f32>().ok())?;
+                // An explicit entry takes precedence over the wildcard.
+                Some((name != "*", quality))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, quality)| quality);
+        if let Some(quality) = quality.filter(|quality| *quality > 0.0) {
+            accepted.push((*encoding, quality));
+        }
+    }
+
+    // Stable, so ties keep the order of preference.
+    accepted.sort_by(|a, b| b.1.total_cmp(&a.1));
+    accepted.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+/// Decodes a precompressed variant while it is read.
+#[cfg(feature = "precompress")]
+enum Decoder {
+    Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+}
+
+#[cfg(feature = "precompress")]
+impl Decoder {
+    fn new(encoding: &str) -> Option<Self> {
+        match encoding {
+            "br" => Some(Self::Brotli(Box::new(brotli::DecompressorWriter::new(
+                Vec::new(),
+                4096,
+            )))),
+            "gzip" => Some(Self::Gzip(flate2::write::GzDecoder::new(Vec::new()))),
+            _ => None,
+        }
+    }
+
+    /// Decodes |data| and returns the bytes decoded so far.
+    fn write(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        use std::io::Write;
+
+        match self {
+            Self::Brotli(writer) => {
+                writer.write_all(data)?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+            Self::Gzip(writer) => {
+                writer.write_all(data)?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+        }
+    }
+
+    /// Returns the remaining bytes once the variant was read completely.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Brotli(writer) => writer
+                .into_inner()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "truncated brotli data")),
+            Self::Gzip(writer) => writer.finish(),
+        }
+    }
+}
+
+/// Without the `precompress` feature there are no variants to decode.
+#[cfg(not(feature = "precompress"))]
+enum Decoder {}
+
+#[cfg(not(feature = "precompress"))]
+impl Decoder {
+    fn new(_encoding: &str) -> Option<Self> {
+        None
+    }
+
+    fn write(&mut self, _data: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {}
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {}
+    }
+}
+
+struct Selected {
+    handler: ResourceHandler,
+    /// Decodes the selected variant, `None` for the identity.
+    decoder: Option<Decoder>,
+    /// Decoded bytes that didn't fit into the last read.
+    decoded: Vec<u8>,
+    offset: usize,
+}
+
+/// Returns a handler that reads a precompressed variant of |resource_path| from
+/// |mount| if the request accepts its encoding, and |identity| otherwise.
+///
+/// CEF passes the body of custom resource handlers to the renderer as is, without
+/// applying `Content-Encoding`, so the variant is decoded here while it is read and
+/// the response is sent without an encoding. Reading the smaller variant still
+/// pays off for the embedded resources and the archive.
+pub fn create_precompressed_resource_handler(
+    mount: Mount,
+    resource_path: String,
+    identity: ResourceHandler,
+) -> ResourceHandler {
+    let selected = Selected {
+        handler: identity,
+        decoder: None,
+        decoded: Vec::new(),
+        offset: 0,
+    };
+    PrecompressedResourceHandler::new(mount, resource_path, Arc::new(Mutex::new(selected)))
+}
+
+wrap_resource_handler! {
+    struct PrecompressedResourceHandler {
+        mount: Mount,
+        resource_path: String,
+        selected: Arc<Mutex<Selected>>,
+    }
+
+    impl ResourceHandler {
+        fn open(
+            &self,
+            request: Option<&mut Request>,
+            handle_request: Option<&mut c_int>,
+            callback: Option<&mut Callback>,
+        ) -> c_int {
+            let mut selected = self.selected.lock().expect("Failed to lock selected");
+
+            let header = |name: &str| {
+                request
+                    .as_ref()
+                    .map(|request| {
+                        CefString::from(&request.header_by_name(Some(&CefString::from(name))))
+                            .to_string()
+                    })
+                    .unwrap_or_default()
+            };
+            // Ranges refer to the decoded body, which only the identity can serve.
+            let accept_encoding = if header("Range").is_empty() {
+                header("Accept-Encoding")
+            } else {
+                String::new()
+            };
+            for encoding in accepted_encodings(&accept_encoding) {
+                let Some((_, suffix)) = PRECOMPRESSED_ENCODINGS
+                    .iter()
+                    .find(|(candidate, _)| *candidate == encoding)
+                else {
+                    continue;
+                };
+                let Some(decoder) = Decoder::new(encoding) else {
+                    continue;
+                };
+                let variant = format!("{}{suffix}", self.resource_path);
+                if let Some(handler) = self.mount.source.resource_handler(&variant, self.mount.symlinks) {
+                    selected.handler = handler;
+                    selected.decoder = Some(decoder);
+                    break;
+                }
+            }
+
+            selected.handler.open(request, handle_request, callback)
+        }
+
+        fn response_headers(
+            &self,
+            response: Option<&mut Response>,
+            response_length: Option<&mut i64>,
+            redirect_url: Option<&mut CefString>,
+        ) {
+            let selected = self.selected.lock().expect("Failed to lock selected");
+            let Some(response) = response else {
+                return;
+            };
+
+            let mut length = -1;
+            selected
+                .handler
+                .response_headers(Some(&mut *response), Some(&mut length), redirect_url);
+
+            response.set_header_by_name(
+                Some(&CefString::from("Vary")),
+                Some(&CefString::from("Accept-Encoding")),
+                1,
+            );
+            if selected.decoder.is_some() {
+                // The variant's mime type is derived from its `.br`/`.gz` suffix, and
+                // the decoded length is unknown.
+                let mime_type = get_mime_type(&self.resource_path);
+                response.set_mime_type(Some(&CefString::from(mime_type.as_str())));
+                length = -1;
+            }
+            if let Some(response_length) = response_length {
+                *response_length = length;
+            }
+        }
+
+        fn read(
+            &self,
+            data_out: *mut u8,
+            bytes_to_read: c_int,
+            bytes_read: Option<&mut c_int>,
+            callback: Option<&mut ResourceReadCallback>,
+        ) -> c_int {
+            let mut selected = self.selected.lock().expect("Failed to lock selected");
+            let selected = &mut *selected;
+            if selected.decoder.is_none() && selected.offset == selected.decoded.len() {
+                return selected
+                    .handler
+                    .read(data_out, bytes_to_read, bytes_read, callback);
+            }
+
+            let capacity = bytes_to_read.max(0) as usize;
+            // The variants are read synchronously from disk or memory.
+            while selected.offset == selected.decoded.len() {
+                let Some(decoder) = selected.decoder.as_mut() else {
+                    break;
+                };
+                let mut buffer = vec![0u8; capacity.max(4096)];
+                let mut read = 0;
+                let more = selected.handler.read(
+                    buffer.as_mut_ptr(),
+                    buffer.len() as c_int,
+                    Some(&mut read),
+                    None,
+                ) != 0
+                    && read > 0;
+                buffer.truncate(read.max(0) as usize);
+                let result = if more {
+                    decoder.write(&buffer)
+                } else {
+                    let decoder = selected.decoder.take().expect("decoder was just found");
+                    decoder.finish()
+                };
+                match result {
+                    Ok(decoded) => {
+                        selected.decoded = decoded;
+                        selected.offset = 0;
+                    }
+                    Err(err) => {
+                        eprintln!("failed to decode {}: {err}", self.resource_path);
+                        selected.decoder = None;
+                        selected.decoded.clear();
+                        selected.offset = 0;
+                    }
+                }
+            }
+
+            let offset = selected.offset;
+            let size = (selected.decoded.len() - offset).min(capacity);
+            if size > 0 {
+                // SAFETY: CEF provides a buffer of at least |bytes_to_read| bytes.
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        selected.decoded[offset..].as_ptr(),
+                        data_out,
+                        size,
+                    );
+                }
+                selected.offset += size;
+            }
+
+            if let Some(bytes_read) = bytes_read {
+                *bytes_read = size as c_int;
+            }
+            (size > 0).into()
+        }
+
+        fn cancel(&self) {
+            let selected = self.selected.lock().expect("Failed to lock selected");
+            selected.handler.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepted_encodings_by_quality() {
+        assert_eq!(accepted_encodings("gzip, deflate, br"), ["br", "gzip"]);
+        assert_eq!(accepted_encodings("br;q=0.5, gzip;q=0.8"), ["gzip", "br"]);
+        // Ties keep the order of preference.
+        assert_eq!(accepted_encodings("gzip;q=0.5, br;q=0.5"), ["br", "gzip"]);
+        assert!(accepted_encodings("").is_empty());
+        assert!(accepted_encodings("identity, deflate").is_empty());
+    }
+
+    #[test]
+    fn accepted_encodings_excluded() {
+        assert_eq!(accepted_encodings("br;q=0, gzip"), ["gzip"]);
+        assert!(accepted_encodings("br;q=0, gzip;q=0.0").is_empty());
+        // Malformed quality values exclude the encoding.
+        assert_eq!(accepted_encodings("br;q=high, gzip"), ["gzip"]);
+    }
+
+    #[test]
+    fn accepted_encodings_wildcard() {
+        assert_eq!(accepted_encodings("*"), ["br", "gzip"]);
+        assert_eq!(accepted_encodings("*;q=0.5, gzip"), ["gzip", "br"]);
+        // An explicit entry wins over the wildcard.
+        assert_eq!(accepted_encodings("*, br;q=0"), ["gzip"]);
+    }
+
+    #[test]
+    fn accepted_encodings_ignore_case() {
+        assert_eq!(accepted_encodings("GZIP, Br"), ["br", "gzip"]);
+        assert_eq!(
+            accepted_encodings(" gzip ; q=0.2 , br ; q=0.1 "),
+            ["gzip", "br"]
+        );
+    }
+
+    #[test]
+    fn precompressed_extensions() {
+        assert_eq!(
+            has_precompressed_variants("js/app.JS"),
+            cfg!(feature = "precompress")
+        );
+        assert!(!has_precompressed_variants("index.html"));
+        assert!(!has_precompressed_variants("README"));
+    }
+
+    #[cfg(feature = "precompress")]
+    #[test]
+    fn decode_variants() {
+        use std::io::Write;
+
+        let data = "console.log('hello');\n".repeat(500).into_bytes();
+
+        let mut brotli = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+            writer.write_all(&data).unwrap();
+        }
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&data).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        for (encoding, compressed) in [("br", brotli), ("gzip", gzip)] {
+            let mut decoder = Decoder::new(encoding).unwrap();
+            let mut decoded = Vec::new();
+            // Feed the variant in small chunks like `read` does.
+            for chunk in compressed.chunks(100) {
+                decoded.extend(decoder.write(chunk).unwrap());
+            }
+            decoded.extend(decoder.finish().unwrap());
+            assert_eq!(decoded, data, "{encoding}");
+        }
+    }
+}
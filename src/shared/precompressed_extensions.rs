@@ -0,0 +1,5 @@
+/// Extensions of the assets build.rs creates precompressed variants for. HTML is
+/// left out so that CSP nonces can be inserted into pages.
+pub const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+    "js", "mjs", "css", "json", "map", "svg", "txt", "wasm", "xml",
+];
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+use cef::*;
+
+use crate::shared::scheme::register_scheme_handler_factories;
+
+/// Name reported for browsers using the global request context.
+pub const DEFAULT_PROFILE: &str = "default";
+
+static PROFILE_ROOT: OnceLock<PathBuf> = OnceLock::new();
+static PROFILES: Mutex<Vec<(String, RequestContext)>> = Mutex::new(Vec::new());
+
+/// Returns the per-user application data directory, e.g.
+/// `~/.local/share/message_router` on Linux.
+fn get_app_data_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+    #[cfg(target_os = "macos")]
+    let base = std::env::home_dir().map(|home| home.join("Library/Application Support"));
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::home_dir().map(|home| home.join(".local/share")));
+
+    Some(base?.join("message_router"))
+}
+
+/// Returns the directory below which every profile keeps its cache, cookies and
+/// storage, in the per-user application data directory. It is also used as
+/// `Settings::root_cache_path`, since CEF requires profile cache paths to be
+/// children of it.
+pub fn get_profile_root() -> PathBuf {
+    PROFILE_ROOT
+        .get_or_init(|| {
+            get_app_data_dir().unwrap_or_else(|| {
+                eprintln!("no application data directory, keeping profiles in the temp directory");
+                std::env::temp_dir().join("message_router")
+            })
+        })
+        .clone()
+}
+
+/// Overrides the profile root. Must be called before CEF is initialized.
+pub fn set_profile_root(path: PathBuf) -> bool {
+    PROFILE_ROOT.set(path).is_ok()
+}
+
+/// Returns true if |name| can be used as a profile name. Names consist of lower
+/// case ASCII letters, digits, `-` and `_`, so every name maps to a directory of
+/// its own, even on case-insensitive file systems.
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Returns the directory the profiles keep their cache paths in. Data the
+/// application stores per profile, e.g. remembered permissions, goes to
+/// `<dir>/<name>` as well, also for the `DEFAULT_PROFILE`.
+pub fn get_profiles_dir() -> PathBuf {
+    get_profile_root().join("profiles")
+}
+
+/// Returns the cache path of the profile |name|. Profiles live in a directory of
+/// their own so that they can't clash with other files below the profile root.
+fn get_profile_path(name: &str) -> PathBuf {
+    get_profiles_dir().join(name)
+}
+
+/// Returns the request context of the profile |name|, creating it on first use.
+/// Returns `None` if |name| isn't a valid profile name, see
+/// `is_valid_profile_name`.
+///
+/// Each profile has its own cache path and therefore separate cookies, cache and
+/// local storage. Must be called on the UI thread after the CEF context has been
+/// initialized.
+pub fn get_profile_request_context(name: &str) -> Option<RequestContext> {
+    debug_assert_ne!(currently_on(ThreadId::UI), 0);
+
+    if name == DEFAULT_PROFILE {
+        return request_context_get_global_context();
+    }
+    if !is_valid_profile_name(name) {
+        eprintln!("invalid profile name {name:?}");
+        return None;
+    }
+
+    let mut profiles = PROFILES.lock().expect("Failed to lock PROFILES");
+    if let Some((_, request_context)) = profiles.iter().find(|(n, _)| n == name) {
+        return Some(request_context.clone());
+    }
+
+    let cache_path = get_profile_path(name);
+    let settings = RequestContextSettings {
+        cache_path: cache_path.to_string_lossy().as_ref().into(),
+        persist_session_cookies: 1,
+        ..Default::default()
+    };
+    let request_context = request_context_create_context(Some(&settings), None)?;
+    // Scheme handler factories are registered per request context.
+    register_scheme_handler_factories(Some(&request_context));
+    profiles.push((name.to_string(), request_context.clone()));
+
+    Some(request_context)
+}
+
+/// Returns the name of the profile |browser| was created with.
+pub fn get_profile_name(browser: &Browser) -> String {
+    let Some(mut request_context) = browser.host().and_then(|host| host.request_context()) else {
+        return DEFAULT_PROFILE.to_string();
+    };
+
+    let profiles = PROFILES.lock().expect("Failed to lock PROFILES");
+    profiles
+        .iter()
+        .find(|(_, item)| item.is_same(Some(&mut request_context)) != 0)
+        .map(|(name, _)| name.clone())
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+/// Returns the profiles created so far together with their cache paths.
+pub fn get_profiles() -> HashMap<String, PathBuf> {
+    let profiles = PROFILES.lock().expect("Failed to lock PROFILES");
+    profiles
+        .iter()
+        .map(|(name, _)| (name.clone(), get_profile_path(name)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_names() {
+        for name in ["work", "account-2", "a_b", DEFAULT_PROFILE] {
+            assert!(is_valid_profile_name(name), "{name}");
+        }
+        for name in ["", "a.b", "..", "a/b", "Work", "a b", "konto\u{e4}"] {
+            assert!(!is_valid_profile_name(name), "{name}");
+        }
+    }
+}
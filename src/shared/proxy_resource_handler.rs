@@ -0,0 +1,382 @@
+use std::{
+    os::raw::{c_int, c_void},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use cef::*;
+
+/// Environment variable selecting the dev server, see `get_dev_server_url`.
+pub const DEV_SERVER_ENV: &str = "MESSAGE_ROUTER_DEV_SERVER";
+
+/// Returns the URL of the development server requests to the app origin should be
+/// forwarded to, from `--dev-server-url=<url>` or the `MESSAGE_ROUTER_DEV_SERVER`
+/// environment variable.
+pub fn get_dev_server_url(command_line: &CommandLine) -> Option<String> {
+    let switch = CefString::from("dev-server-url");
+    let url = if command_line.has_switch(Some(&switch)) != 0 {
+        CefString::from(&command_line.switch_value(Some(&switch))).to_string()
+    } else {
+        std::env::var(DEV_SERVER_ENV).ok()?
+    };
+
+    let url = url.trim();
+    if url.is_empty() {
+        return None;
+    }
+    if url.ends_with('/') {
+        Some(url.to_string())
+    } else {
+        Some(format!("{url}/"))
+    }
+}
+
+/// Upstream headers that describe the encoding of the original body. The body is
+/// passed on decoded and streamed, so they no longer apply.
+const HOP_HEADERS: [&str; 3] = ["content-encoding", "content-length", "transfer-encoding"];
+
+/// Returns the upstream redirect target |location| with the dev server at
+/// |target_base| replaced by the app origin at |prefix|, so the page doesn't
+/// leave its app URL. Other targets, including relative ones, are kept.
+fn rewrite_location(location: &str, prefix: &str, target_base: &str) -> String {
+    match location.strip_prefix(target_base.trim_end_matches('/')) {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '?', '#']) => {
+            format!("{}{rest}", prefix.trim_end_matches('/'))
+        }
+        _ => location.to_string(),
+    }
+}
+
+/// A read that waits for more upstream data.
+struct PendingRead {
+    data_out: *mut u8,
+    len: usize,
+    callback: ResourceReadCallback,
+}
+
+// SAFETY: CEF keeps |data_out| valid until |callback| is executed, and only the
+// handler accesses it meanwhile, with the state locked.
+unsafe impl Send for PendingRead {}
+
+#[derive(Default)]
+struct ProxyState {
+    /// The upstream response, `None` if the upstream request failed.
+    response: Option<Response>,
+    /// Upstream data that wasn't read yet.
+    data: Vec<u8>,
+    /// Set once the upstream request completed, so no more data follows.
+    complete: bool,
+    /// Continues the request once the upstream response headers arrived.
+    open_callback: Option<Callback>,
+    read: Option<PendingRead>,
+    url_request: Option<Urlrequest>,
+}
+
+impl ProxyState {
+    /// Copies up to |len| bytes of the buffered data to |data_out|. Returns the
+    /// number of bytes copied.
+    fn take_data(&mut self, data_out: *mut u8, len: usize) -> usize {
+        let size = self.data.len().min(len);
+        if size > 0 {
+            // SAFETY: CEF provides a buffer of at least |len| bytes.
+            unsafe {
+                std::ptr::copy_nonoverlapping(self.data.as_ptr(), data_out, size);
+            }
+            self.data.drain(..size);
+        }
+        size
+    }
+}
+
+/// Continues the open and read callbacks of |state| that can make progress. The
+/// lock is released first, since CEF may call back into the handler.
+fn continue_callbacks(mut state: MutexGuard<ProxyState>) {
+    // The request can be continued once the headers arrived or it failed.
+    let open_callback = if state.response.is_some() || state.complete {
+        state.open_callback.take()
+    } else {
+        None
+    };
+    let read = match state.read.take() {
+        Some(read) if !state.data.is_empty() => {
+            let size = state.take_data(read.data_out, read.len);
+            Some((read.callback, size as c_int))
+        }
+        Some(read) if state.complete => Some((read.callback, 0)),
+        read => {
+            state.read = read;
+            None
+        }
+    };
+    drop(state);
+
+    if let Some(callback) = open_callback {
+        callback.cont();
+    }
+    if let Some((callback, size)) = read {
+        callback.cont(size);
+    }
+}
+
+/// Returns a handler forwarding requests below |prefix| to the same path below
+/// |target_base|, e.g. from `https://example.com/` to `http://127.0.0.1:5173/`.
+///
+/// The page keeps its logical URL, so origin based checks such as the message
+/// router trust rules keep working while the content comes from the dev server.
+/// The upstream body is streamed as it arrives, e.g. for server-sent events, and
+/// the headers are passed on with its first chunk. Websockets, such as the hot
+/// module replacement socket, can't be proxied and have to connect to the dev
+/// server directly.
+pub fn create_proxy_resource_handler(prefix: &str, target_base: &str) -> ResourceHandler {
+    ProxyResourceHandler::new(
+        prefix.to_string(),
+        target_base.to_string(),
+        Arc::new(Mutex::new(ProxyState::default())),
+    )
+}
+
+wrap_resource_handler! {
+    struct ProxyResourceHandler {
+        prefix: String,
+        target_base: String,
+        state: Arc<Mutex<ProxyState>>,
+    }
+
+    impl ResourceHandler {
+        fn open(
+            &self,
+            request: Option<&mut Request>,
+            handle_request: Option<&mut c_int>,
+            callback: Option<&mut Callback>,
+        ) -> c_int {
+            let (Some(request), Some(handle_request)) = (request, handle_request) else {
+                return 0;
+            };
+            // The request is continued asynchronously from the URL request client.
+            *handle_request = 0;
+
+            let url = CefString::from(&request.url()).to_string();
+            let Some(path) = url.strip_prefix(self.prefix.as_str()) else {
+                return 0;
+            };
+            let target_url = format!("{}{path}", self.target_base);
+
+            let Some(mut upstream) = request_create() else {
+                return 0;
+            };
+            let mut headers = CefStringMultimap::new();
+            request.header_map(Some(&mut headers));
+            let mut post_data = request.post_data();
+            upstream.set(
+                Some(&CefString::from(target_url.as_str())),
+                Some(&CefString::from(&request.method())),
+                post_data.as_mut(),
+                Some(&mut headers),
+            );
+            // Redirects are passed on to the page, see `rewrite_location`.
+            upstream.set_flags(sys::cef_urlrequest_flags_t::UR_FLAG_STOP_ON_REDIRECT as c_int);
+
+            self.state.lock().expect("Failed to lock state").open_callback = callback.cloned();
+
+            // Don't hold the lock here in case the client is notified synchronously.
+            let mut client = ProxyUrlrequestClient::new(self.state.clone());
+            let url_request = urlrequest_create(Some(&mut upstream), Some(&mut client), None);
+
+            let mut state = self.state.lock().expect("Failed to lock state");
+            let started = url_request.is_some();
+            if !state.complete {
+                state.url_request = url_request;
+            }
+            started.into()
+        }
+
+        fn response_headers(
+            &self,
+            response: Option<&mut Response>,
+            response_length: Option<&mut i64>,
+            _redirect_url: Option<&mut CefString>,
+        ) {
+            let state = self.state.lock().expect("Failed to lock state");
+            let Some(response) = response else {
+                return;
+            };
+
+            let length = match state.response.as_ref() {
+                Some(upstream) => {
+                    response.set_status(upstream.status());
+                    response.set_status_text(Some(&CefString::from(&upstream.status_text())));
+                    response.set_mime_type(Some(&CefString::from(&upstream.mime_type())));
+                    response.set_charset(Some(&CefString::from(&upstream.charset())));
+
+                    let mut header_map = CefStringMultimap::new();
+                    upstream.header_map(Some(&mut header_map));
+                    for (name, mut value) in Vec::<(String, String)>::from(&header_map) {
+                        let name_lower = name.to_ascii_lowercase();
+                        if HOP_HEADERS.contains(&name_lower.as_str()) {
+                            continue;
+                        }
+                        if name_lower == "location" {
+                            value = rewrite_location(&value, &self.prefix, &self.target_base);
+                        }
+                        response.set_header_by_name(
+                            Some(&CefString::from(name.as_str())),
+                            Some(&CefString::from(value.as_str())),
+                            0,
+                        );
+                    }
+                    // The length of the decoded body isn't known until it ends.
+                    -1
+                }
+                None => {
+                    response.set_status(502);
+                    response.set_status_text(Some(&CefString::from("Bad Gateway")));
+                    response.set_mime_type(Some(&CefString::from("text/plain")));
+                    response.set_header_by_name(
+                        Some(&CefString::from("Content-Length")),
+                        Some(&CefString::from(state.data.len().to_string().as_str())),
+                        1,
+                    );
+                    state.data.len() as i64
+                }
+            };
+            if let Some(response_length) = response_length {
+                *response_length = length;
+            }
+        }
+
+        fn read(
+            &self,
+            data_out: *mut u8,
+            bytes_to_read: c_int,
+            bytes_read: Option<&mut c_int>,
+            callback: Option<&mut ResourceReadCallback>,
+        ) -> c_int {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            let len = bytes_to_read.max(0) as usize;
+            let size = state.take_data(data_out, len);
+            if let Some(bytes_read) = bytes_read {
+                *bytes_read = size as c_int;
+            }
+            if size > 0 {
+                return 1;
+            }
+            if state.complete {
+                return 0;
+            }
+
+            // Wait for more upstream data, the URL request client executes
+            // |callback|.
+            let Some(callback) = callback.cloned() else {
+                return 0;
+            };
+            state.read = Some(PendingRead {
+                data_out,
+                len,
+                callback,
+            });
+            1
+        }
+
+        fn cancel(&self) {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            state.open_callback = None;
+            state.read = None;
+            state.complete = true;
+            if let Some(url_request) = state.url_request.take() {
+                url_request.cancel();
+            }
+        }
+    }
+}
+
+wrap_urlrequest_client! {
+    struct ProxyUrlrequestClient {
+        state: Arc<Mutex<ProxyState>>,
+    }
+
+    impl UrlrequestClient {
+        fn on_request_complete(&self, request: Option<&mut Urlrequest>) {
+            let mut state = self.state.lock().expect("Failed to lock state");
+            state.url_request = None;
+            if state.complete {
+                // Canceled.
+                return;
+            }
+            state.complete = true;
+
+            let status = request
+                .as_ref()
+                .map(|request| request.request_status())
+                .unwrap_or(UrlrequestStatus::FAILED);
+            if state.open_callback.is_some() && state.response.is_none() {
+                // Redirects end the request without success, see
+                // `UR_FLAG_STOP_ON_REDIRECT`.
+                state.response = request
+                    .and_then(|request| request.response())
+                    .filter(|response| {
+                        status == UrlrequestStatus::SUCCESS
+                            || (300..400).contains(&response.status())
+                    });
+                if state.response.is_none() {
+                    // The dev server isn't reachable, answer with a 502 instead.
+                    state.data = b"The development server could not be reached.".to_vec();
+                }
+            } else if status != UrlrequestStatus::SUCCESS {
+                eprintln!("the response of the development server ended early");
+            }
+            continue_callbacks(state);
+        }
+
+        fn on_download_data(
+            &self,
+            request: Option<&mut Urlrequest>,
+            data: *const c_void,
+            data_length: usize,
+        ) {
+            if data.is_null() {
+                return;
+            }
+
+            // SAFETY: CEF provides |data_length| bytes at |data|.
+            let data = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), data_length) };
+            let mut state = self.state.lock().expect("Failed to lock state");
+            if state.complete {
+                return;
+            }
+            if state.response.is_none() {
+                state.response = request.and_then(|request| request.response());
+            }
+            state.data.extend_from_slice(data);
+            continue_callbacks(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        let rewrite =
+            |location| rewrite_location(location, "https://example.com/", "http://127.0.0.1:5173/");
+
+        assert_eq!(
+            rewrite("http://127.0.0.1:5173/login?next=/"),
+            "https://example.com/login?next=/"
+        );
+        assert_eq!(rewrite("http://127.0.0.1:5173"), "https://example.com");
+        assert_eq!(
+            rewrite("http://127.0.0.1:5173#top"),
+            "https://example.com#top"
+        );
+        assert_eq!(
+            rewrite("http://127.0.0.1:51730/"),
+            "http://127.0.0.1:51730/"
+        );
+        assert_eq!(
+            rewrite("https://auth.example.org/"),
+            "https://auth.example.org/"
+        );
+        assert_eq!(rewrite("/login"), "/login");
+    }
+}
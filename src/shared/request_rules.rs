@@ -0,0 +1,455 @@
+use std::sync::{Arc, Mutex};
+
+use cef::*;
+
+use crate::shared::resource_util::get_url_without_query_or_fragment;
+
+/// Returns true if |url| matches |pattern|, where `*` matches any sequence of
+/// characters, e.g. `https://*.doubleclick.net/*`.
+pub fn matches_pattern(pattern: &str, url: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = url.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // The last part has to match the end of the URL.
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+/// Returns true if |reference| starts with a scheme, e.g. `https:` or `data:`.
+fn has_scheme(reference: &str) -> bool {
+    reference.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Returns |path| with its `.` and `..` segments applied.
+fn remove_dot_segments(path: &str) -> String {
+    let mut output = Vec::new();
+    let mut segments = path.split('/').skip(1).peekable();
+    while let Some(segment) = segments.next() {
+        let last = segments.peek().is_none();
+        match segment {
+            "." => {}
+            ".." => {
+                output.pop();
+            }
+            segment => {
+                output.push(segment);
+                continue;
+            }
+        }
+        // Keep the trailing slash of `/a/..` and `/a/.`.
+        if last {
+            output.push("");
+        }
+    }
+    format!("/{}", output.join("/"))
+}
+
+/// Returns |reference| resolved against the absolute URL |base|, e.g. `/new`
+/// against `https://example.com/old` as `https://example.com/new`.
+pub fn resolve_url(base: &str, reference: &str) -> String {
+    if has_scheme(reference) {
+        return reference.to_string();
+    }
+    let Some((scheme, rest)) = base.split_once("://") else {
+        return reference.to_string();
+    };
+    if reference.starts_with("//") {
+        return format!("{scheme}:{reference}");
+    }
+    if reference.is_empty() || reference.starts_with('#') {
+        let base = base.split('#').next().unwrap_or_default();
+        return format!("{base}{reference}");
+    }
+    if reference.starts_with('?') {
+        return format!("{}{reference}", get_url_without_query_or_fragment(base));
+    }
+
+    let authority_len = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let origin = &base[..scheme.len() + "://".len() + authority_len];
+    let (path, suffix) = reference.split_at(reference.find(['?', '#']).unwrap_or(reference.len()));
+    let path = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        // Relative to the directory of the base path.
+        let base_path = get_url_without_query_or_fragment(&rest[authority_len..]);
+        let directory = base_path.rfind('/').map_or("/", |pos| &base_path[..=pos]);
+        format!("{directory}{path}")
+    };
+    format!("{origin}{}{suffix}", remove_dot_segments(&path))
+}
+
+/// Decides the fate of a request asynchronously. Exactly one of `allow`, `block`
+/// or `redirect` should be called; dropping the decision allows the request.
+///
+/// The callback runs in a task on the IO thread, never while the `ClientManager`
+/// is locked.
+pub type RuleCallback = Arc<dyn Fn(RuleDecision) + Send + Sync>;
+
+#[derive(Clone)]
+pub enum RuleAction {
+    /// Sets a request header, replacing any existing value, e.g. an auth token for
+    /// an API host.
+    SetHeader(String, String),
+    /// Redirects the request. If the pattern ends with `*`, the part of the URL
+    /// matched by it is appended to the target.
+    Redirect(String),
+    /// Cancels the request, e.g. for ads or telemetry.
+    Block,
+    /// Lets Rust code decide, possibly after the rule engine returned.
+    Decide(RuleCallback),
+}
+
+#[derive(Clone)]
+struct RequestRule {
+    pattern: String,
+    action: RuleAction,
+}
+
+impl RequestRule {
+    /// Returns the absolute URL |url| is redirected to, with |target| resolved
+    /// against |url|.
+    fn redirect_url(&self, target: &str, url: &str) -> String {
+        let target = match self.pattern.strip_suffix('*') {
+            Some(prefix) if !prefix.contains('*') => {
+                format!("{target}{}", url.strip_prefix(prefix).unwrap_or_default())
+            }
+            _ => target.to_string(),
+        };
+        resolve_url(url, &target)
+    }
+}
+
+/// Continues a request once the rules are applied. Returns the `ReturnValue` of
+/// `on_before_resource_load`.
+pub type ContinueRequest = Arc<dyn Fn(Request, Option<Callback>) -> ReturnValue + Send + Sync>;
+
+/// Rules applied to requests in `on_before_resource_load`, in the order they were
+/// added. Header rules accumulate, the first matching `Redirect`, `Block` or
+/// `Decide` rule ends the evaluation.
+#[derive(Clone, Default)]
+pub struct RequestRules {
+    rules: Vec<RequestRule>,
+}
+
+impl RequestRules {
+    pub fn add(&mut self, pattern: &str, action: RuleAction) {
+        self.rules.push(RequestRule {
+            pattern: pattern.to_string(),
+            action,
+        });
+    }
+
+    /// Removes the rules for |pattern|. Returns false if there were none.
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.pattern != pattern);
+        self.rules.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Applies the rules to |request|. Requests that are allowed are passed on to
+    /// |next| with |callback|, possibly after this returned `CONTINUE_ASYNC`.
+    pub fn apply(
+        &self,
+        request: Request,
+        callback: Option<Callback>,
+        next: ContinueRequest,
+    ) -> ReturnValue {
+        let url = CefString::from(&request.url()).to_string();
+
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| matches_pattern(&rule.pattern, &url))
+        {
+            match &rule.action {
+                RuleAction::SetHeader(name, value) => set_header(&request, name, value),
+                RuleAction::Redirect(target) => {
+                    // Changing the URL makes CEF redirect the request.
+                    let target = rule.redirect_url(target, &url);
+                    request.set_url(Some(&CefString::from(target.as_str())));
+                    return ReturnValue::CONTINUE;
+                }
+                RuleAction::Block => return ReturnValue::CANCEL,
+                RuleAction::Decide(decide) => {
+                    let Some(callback) = callback else {
+                        eprintln!("no callback to decide about {url} asynchronously");
+                        return ReturnValue::CANCEL;
+                    };
+                    // The rules are applied while the `ClientManager` is locked, so the
+                    // callback is called later.
+                    let pending = PendingDecision {
+                        request,
+                        callback,
+                        next,
+                    };
+                    let mut task =
+                        StartDecision::new(decide.clone(), Arc::new(Mutex::new(Some(pending))));
+                    post_task(ThreadId::IO, Some(&mut task));
+                    return ReturnValue::CONTINUE_ASYNC;
+                }
+            }
+        }
+
+        next(request, callback)
+    }
+}
+
+fn set_header(request: &Request, name: &str, value: &str) {
+    request.set_header_by_name(
+        Some(&CefString::from(name)),
+        Some(&CefString::from(value)),
+        1,
+    );
+}
+
+struct PendingDecision {
+    request: Request,
+    callback: Callback,
+    next: ContinueRequest,
+}
+
+/// A request waiting for the decision of a `RuleAction::Decide` rule. It can be
+/// decided on any thread; the request is continued on the IO thread.
+pub struct RuleDecision {
+    inner: Mutex<Option<PendingDecision>>,
+}
+
+impl RuleDecision {
+    pub fn url(&self) -> String {
+        self.with_request(|request| CefString::from(&request.url()).to_string())
+    }
+
+    pub fn method(&self) -> String {
+        self.with_request(|request| CefString::from(&request.method()).to_string())
+    }
+
+    pub fn header(&self, name: &str) -> String {
+        self.with_request(|request| {
+            CefString::from(&request.header_by_name(Some(&CefString::from(name)))).to_string()
+        })
+    }
+
+    /// Sets a header of the request before it is allowed.
+    pub fn set_header(&self, name: &str, value: &str) {
+        self.with_request(|request| set_header(request, name, value));
+    }
+
+    pub fn allow(self) {
+        self.decide(None);
+    }
+
+    /// Redirects the request to |url|, which may be relative to the request URL.
+    pub fn redirect(self, url: &str) {
+        self.decide(Some(url));
+    }
+
+    pub fn block(self) {
+        if let Some(pending) = self.inner.lock().expect("Failed to lock inner").take() {
+            pending.callback.cancel();
+        }
+    }
+
+    fn with_request<T: Default>(&self, f: impl FnOnce(&Request) -> T) -> T {
+        let inner = self.inner.lock().expect("Failed to lock inner");
+        inner
+            .as_ref()
+            .map(|pending| f(&pending.request))
+            .unwrap_or_default()
+    }
+
+    fn decide(&self, redirect_url: Option<&str>) {
+        let Some(pending) = self.inner.lock().expect("Failed to lock inner").take() else {
+            return;
+        };
+        let redirect_url = redirect_url.map(str::to_string);
+
+        if currently_on(ThreadId::IO) == 0 {
+            // Execute on the IO thread.
+            let mut task = ContinueDecision::new(Arc::new(Mutex::new(Some(pending))), redirect_url);
+            post_task(ThreadId::IO, Some(&mut task));
+            return;
+        }
+        continue_request(pending, redirect_url);
+    }
+}
+
+impl Drop for RuleDecision {
+    fn drop(&mut self) {
+        self.decide(None);
+    }
+}
+
+fn continue_request(pending: PendingDecision, redirect_url: Option<String>) {
+    debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+    if let Some(url) = redirect_url {
+        let url = resolve_url(&CefString::from(&pending.request.url()).to_string(), &url);
+        pending
+            .request
+            .set_url(Some(&CefString::from(url.as_str())));
+        pending.callback.cont();
+        return;
+    }
+
+    let callback = pending.callback.clone();
+    let ret = (pending.next)(pending.request, Some(pending.callback));
+    // Otherwise |next| continues the request itself.
+    if ret == ReturnValue::CONTINUE {
+        callback.cont();
+    } else if ret == ReturnValue::CANCEL {
+        callback.cancel();
+    }
+}
+
+wrap_task! {
+    struct StartDecision {
+        decide: RuleCallback,
+        pending: Arc<Mutex<Option<PendingDecision>>>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+            let pending = self.pending.lock().expect("Failed to lock pending").take();
+            (self.decide)(RuleDecision {
+                inner: Mutex::new(pending),
+            });
+        }
+    }
+}
+
+wrap_task! {
+    struct ContinueDecision {
+        pending: Arc<Mutex<Option<PendingDecision>>>,
+        redirect_url: Option<String>,
+    }
+
+    impl Task {
+        fn execute(&self) {
+            let pending = self.pending.lock().expect("Failed to lock pending").take();
+            if let Some(pending) = pending {
+                continue_request(pending, self.redirect_url.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_without_wildcards() {
+        assert!(matches_pattern(
+            "https://example.com/",
+            "https://example.com/"
+        ));
+        assert!(!matches_pattern(
+            "https://example.com/",
+            "https://example.com/a"
+        ));
+        assert!(!matches_pattern(
+            "https://example.com/a",
+            "https://example.com/"
+        ));
+    }
+
+    #[test]
+    fn pattern_with_wildcards() {
+        let pattern = "https://*.doubleclick.net/*";
+        assert!(matches_pattern(pattern, "https://ad.doubleclick.net/x.js"));
+        assert!(matches_pattern(pattern, "https://.doubleclick.net/"));
+        assert!(!matches_pattern(pattern, "https://doubleclick.net/x.js"));
+        assert!(!matches_pattern(pattern, "http://ad.doubleclick.net/x.js"));
+
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("*.js", "https://example.com/app.js"));
+        assert!(!matches_pattern("*.js", "https://example.com/app.json"));
+        assert!(matches_pattern("a*b*c", "abc"));
+        assert!(matches_pattern("a*b*c", "axxbyybc"));
+        assert!(!matches_pattern("a*b*c", "acb"));
+        // The parts must not overlap.
+        assert!(!matches_pattern("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn redirect_url() {
+        let rule = |pattern: &str| RequestRule {
+            pattern: pattern.to_string(),
+            action: RuleAction::Block,
+        };
+        assert_eq!(
+            rule("https://api.example.com/*").redirect_url(
+                "http://localhost:8080/",
+                "https://api.example.com/v1/users?id=1"
+            ),
+            "http://localhost:8080/v1/users?id=1"
+        );
+        assert_eq!(
+            rule("https://*.example.com/*")
+                .redirect_url("http://localhost/", "https://api.example.com/v1"),
+            "http://localhost/"
+        );
+        assert_eq!(
+            rule("https://example.com/old").redirect_url("/new", "https://example.com/old"),
+            "https://example.com/new"
+        );
+        assert_eq!(
+            rule("https://example.com/docs/*")
+                .redirect_url("/v2/", "https://example.com/docs/a/b.html?x=1"),
+            "https://example.com/v2/a/b.html?x=1"
+        );
+    }
+
+    #[test]
+    fn resolve_urls() {
+        let base = "https://example.com:8443/a/b/page.html?q=1#top";
+        for (reference, resolved) in [
+            ("http://other.com/x", "http://other.com/x"),
+            ("data:text/plain,hi", "data:text/plain,hi"),
+            ("//cdn.example.com/x.js", "https://cdn.example.com/x.js"),
+            ("/new", "https://example.com:8443/new"),
+            ("/new?x#y", "https://example.com:8443/new?x#y"),
+            ("other.html", "https://example.com:8443/a/b/other.html"),
+            ("./other.html", "https://example.com:8443/a/b/other.html"),
+            ("../c/", "https://example.com:8443/a/c/"),
+            ("../../../up", "https://example.com:8443/up"),
+            ("..", "https://example.com:8443/a/"),
+            ("?page=2", "https://example.com:8443/a/b/page.html?page=2"),
+            ("#end", "https://example.com:8443/a/b/page.html?q=1#end"),
+            ("", "https://example.com:8443/a/b/page.html?q=1"),
+        ] {
+            assert_eq!(resolve_url(base, reference), resolved, "{reference}");
+        }
+        assert_eq!(
+            resolve_url("https://example.com", "new"),
+            "https://example.com/new"
+        );
+        assert_eq!(
+            resolve_url("https://example.com?q", "/new"),
+            "https://example.com/new"
+        );
+    }
+}
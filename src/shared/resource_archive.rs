@@ -0,0 +1,138 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, bail};
+use cef::{wrapper::resource_manager::get_mime_type, *};
+use sha2::{Digest, Sha256};
+
+use crate::shared::{resource_mount::ResourceProvider, resource_util::get_data_resource_handler};
+
+/// File name of the resource archive created by build.rs.
+pub const RESOURCE_ARCHIVE_NAME: &str = "message_router.zip";
+
+/// SHA-256 of the resource archive, recorded by build.rs.
+pub const RESOURCE_ARCHIVE_SHA256: &str = env!("RESOURCE_ARCHIVE_SHA256");
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Returns a zip reader for the archive |data|. The stream reads |data| in place.
+fn create_zip_reader(data: &[u8]) -> Option<ZipReader> {
+    let mut stream = stream_reader_create_for_data(data.as_ptr().cast_mut().cast(), data.len())?;
+    zip_reader_create(Some(&mut stream))
+}
+
+/// Decompresses the current file of |reader|, which is |size| bytes large.
+fn read_current_file(reader: &ZipReader, size: usize) -> Option<Vec<u8>> {
+    if reader.open_file(None) == 0 {
+        return None;
+    }
+
+    let mut contents = Vec::with_capacity(size);
+    let mut buffer = [0u8; 8192];
+    let result = loop {
+        let read = reader.read_file(buffer.as_mut_ptr().cast(), buffer.len());
+        if read < 0 {
+            break None;
+        }
+        if read == 0 {
+            break Some(contents);
+        }
+        contents.extend_from_slice(&buffer[..read as usize]);
+    };
+    reader.close_file();
+    result
+}
+
+/// Serves files out of a zip archive.
+///
+/// The archive is read and verified once when it is opened and then kept in
+/// memory, so that it can't change between the verification and later reads.
+/// Only an index of its files is built up front; a file is decompressed when it
+/// is requested.
+pub struct ArchiveProvider {
+    data: Vec<u8>,
+    /// Uncompressed size of every file, by name.
+    index: HashMap<String, usize>,
+}
+
+impl ArchiveProvider {
+    /// Opens the archive at |path|. If |expected_sha256| is given the archive is
+    /// rejected unless its SHA-256 matches.
+    pub fn open(path: &Path, expected_sha256: Option<&str>) -> anyhow::Result<Self> {
+        let data =
+            fs::read(path).with_context(|| format!("failed to read archive {}", path.display()))?;
+
+        if let Some(expected_sha256) = expected_sha256 {
+            let sha256 = to_hex(&Sha256::digest(&data));
+            if !sha256.eq_ignore_ascii_case(expected_sha256) {
+                bail!(
+                    "archive {} failed integrity verification: expected {expected_sha256}, got {sha256}",
+                    path.display()
+                );
+            }
+        }
+
+        let reader = create_zip_reader(&data).context("failed to open the archive")?;
+        let mut index = HashMap::new();
+        let mut has_file = reader.move_to_first_file() != 0;
+        while has_file {
+            let name = CefString::from(&reader.file_name()).to_string();
+            // Directories are listed with a trailing '/'.
+            if !name.ends_with('/') {
+                let size = usize::try_from(reader.file_size()).unwrap_or_default();
+                index.insert(name, size);
+            }
+            has_file = reader.move_to_next_file() != 0;
+        }
+        reader.close();
+
+        Ok(Self { data, index })
+    }
+
+    /// Opens the archive created by build.rs next to the executable and verifies
+    /// it against the hash recorded at build time.
+    pub fn open_default() -> anyhow::Result<Self> {
+        let path = std::env::current_exe()?
+            .parent()
+            .context("executable has no parent directory")?
+            .join(RESOURCE_ARCHIVE_NAME);
+        Self::open(&path, Some(RESOURCE_ARCHIVE_SHA256))
+    }
+
+    pub fn contains(&self, resource_path: &str) -> bool {
+        self.index.contains_key(resource_path)
+    }
+
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+
+    /// Returns the decompressed contents of |resource_path|.
+    pub fn read(&self, resource_path: &str) -> Option<Vec<u8>> {
+        let size = *self.index.get(resource_path)?;
+
+        // Zip readers must be used on the thread that created them, so every read
+        // gets its own.
+        let reader = create_zip_reader(&self.data)?;
+        let file_name = CefString::from(resource_path);
+        let contents = if reader.move_to_file(Some(&file_name), 1) != 0 {
+            read_current_file(&reader, size)
+        } else {
+            None
+        };
+        reader.close();
+
+        if contents.is_none() {
+            eprintln!("failed to read {resource_path} from the resource archive");
+        }
+        contents
+    }
+}
+
+impl ResourceProvider for ArchiveProvider {
+    fn resource_handler(&self, resource_path: &str) -> Option<ResourceHandler> {
+        let data = self.read(resource_path)?;
+        get_data_resource_handler(&get_mime_type(resource_path), data)
+    }
+}
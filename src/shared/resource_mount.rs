@@ -0,0 +1,224 @@
+use std::{path::PathBuf, sync::Arc};
+
+use cef::*;
+
+use crate::shared::{
+    local_resource_handler::ResponsePolicy,
+    precompressed::{create_precompressed_resource_handler, has_precompressed_variants},
+    proxy_resource_handler::create_proxy_resource_handler,
+    resource_util::{
+        SymlinkPolicy, TEST_ORIGIN, get_file_resource_handler, get_resource_handler,
+        get_url_without_query_or_fragment, join_resource_path, normalize_resource_path,
+    },
+};
+
+/// Produces resource handlers for paths relative to a mount prefix.
+pub trait ResourceProvider: Send + Sync {
+    fn resource_handler(&self, resource_path: &str) -> Option<ResourceHandler>;
+}
+
+/// Where the resources of a mount come from.
+#[derive(Clone)]
+pub enum MountSource {
+    /// Files below a directory on disk.
+    Directory(PathBuf),
+    /// The resources bundled with the application (the `Resources` directory of the
+    /// app bundle on macOS, `files` next to the executable on Linux, binary
+    /// resources on Windows). Paths are prefixed with the given string.
+    Bundle(String),
+    /// A custom provider.
+    Provider(Arc<dyn ResourceProvider>),
+    /// Forwards requests to the same path below this base URL, e.g. a local dev
+    /// server at `http://127.0.0.1:5173/`.
+    Proxy(String),
+}
+
+impl MountSource {
+    /// Returns the handler for the normalized |resource_path|. |symlinks| applies
+    /// to directories, bundled files always have to stay within the bundle.
+    pub fn resource_handler(
+        &self,
+        resource_path: &str,
+        symlinks: SymlinkPolicy,
+    ) -> Option<ResourceHandler> {
+        match self {
+            Self::Directory(directory) => {
+                let path = join_resource_path(directory, resource_path, symlinks)?;
+                get_file_resource_handler(&path, resource_path)
+            }
+            Self::Bundle(prefix) => get_resource_handler(&format!("{prefix}{resource_path}")),
+            Self::Provider(provider) => provider.resource_handler(resource_path),
+            // Proxied requests need the complete URL, see `Mount::resource_handler`.
+            Self::Proxy(_) => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Mount {
+    /// URL prefix, e.g. `app://main/` or `https://app.local/assets/`.
+    pub prefix: String,
+    pub source: MountSource,
+    /// Status code, header and error page handling of the responses.
+    pub policy: ResponsePolicy,
+    /// Single-page-app mode: unknown paths without a file extension are answered
+    /// with this resource (usually `index.html`) so client-side routes survive a
+    /// reload. Unknown paths with an extension still result in a 404.
+    pub spa_fallback: Option<String>,
+    /// Whether symbolic links below a directory mount are served.
+    pub symlinks: SymlinkPolicy,
+}
+
+/// Returns true if the last segment of |resource_path| has a file extension.
+fn has_file_extension(resource_path: &str) -> bool {
+    resource_path
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name.rfind('.').is_some_and(|pos| pos > 0))
+}
+
+impl Mount {
+    /// Returns the handler for the URL path |resource_path| relative to the prefix.
+    /// The path is percent-decoded exactly once, here; everything below works on
+    /// the decoded path.
+    pub fn resource_handler(&self, resource_path: &str) -> Option<ResourceHandler> {
+        if let MountSource::Proxy(target_base) = &self.source {
+            // The proxy forwards the complete URL including the query, which the
+            // resource path lacks.
+            return Some(create_proxy_resource_handler(&self.prefix, target_base));
+        }
+
+        // Paths that escape the mount root are never served.
+        let resource_path = normalize_resource_path(resource_path)?;
+        if let Some(handler) = self.source.resource_handler(&resource_path, self.symlinks) {
+            if has_precompressed_variants(&resource_path) {
+                return Some(create_precompressed_resource_handler(
+                    self.clone(),
+                    resource_path,
+                    handler,
+                ));
+            }
+            return Some(handler);
+        }
+
+        let spa_fallback = self.spa_fallback.as_deref()?;
+        if has_file_extension(&resource_path) || resource_path == spa_fallback {
+            return None;
+        }
+        self.source.resource_handler(spa_fallback, self.symlinks)
+    }
+}
+
+/// Maps URL prefixes to resource sources. The longest matching prefix wins.
+#[derive(Clone, Default)]
+pub struct MountTable {
+    mounts: Vec<Mount>,
+}
+
+impl MountTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The table used by this example: everything under `TEST_ORIGIN` is served from
+    /// the application bundle.
+    pub fn with_default_mounts() -> Self {
+        let mut table = Self::new();
+        table.mount(TEST_ORIGIN, MountSource::Bundle(String::new()));
+        table
+    }
+
+    /// Adds or replaces the mount for |prefix|. A trailing `/` is appended to
+    /// |prefix| if it is missing.
+    pub fn mount(&mut self, prefix: &str, source: MountSource) -> &mut Self {
+        self.mount_with_policy(prefix, source, ResponsePolicy::default())
+    }
+
+    pub fn mount_with_policy(
+        &mut self,
+        prefix: &str,
+        source: MountSource,
+        policy: ResponsePolicy,
+    ) -> &mut Self {
+        let mut prefix = prefix.to_string();
+        if !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+
+        self.mounts.retain(|mount| mount.prefix != prefix);
+        self.mounts.push(Mount {
+            prefix,
+            source,
+            policy,
+            spa_fallback: None,
+            symlinks: SymlinkPolicy::default(),
+        });
+        // Keep the longest prefixes first so that nested mounts take precedence.
+        self.mounts
+            .sort_by(|a, b| b.prefix.len().cmp(&a.prefix.len()));
+        self
+    }
+
+    /// Enables the single-page-app mode of the mount for |prefix|, answering unknown
+    /// non-asset paths with |index|. Returns false if there is no such mount.
+    pub fn set_spa_fallback(&mut self, prefix: &str, index: Option<&str>) -> bool {
+        let Some(mount) = self
+            .mounts
+            .iter_mut()
+            .find(|mount| mount.prefix.trim_end_matches('/') == prefix.trim_end_matches('/'))
+        else {
+            return false;
+        };
+
+        mount.spa_fallback = index.map(str::to_string);
+        true
+    }
+
+    /// Sets how the mount for |prefix| treats symbolic links. Returns false if there
+    /// is no such mount.
+    pub fn set_symlink_policy(&mut self, prefix: &str, symlinks: SymlinkPolicy) -> bool {
+        let Some(mount) = self
+            .mounts
+            .iter_mut()
+            .find(|mount| mount.prefix.trim_end_matches('/') == prefix.trim_end_matches('/'))
+        else {
+            return false;
+        };
+
+        mount.symlinks = symlinks;
+        true
+    }
+
+    pub fn unmount(&mut self, prefix: &str) -> bool {
+        let len = self.mounts.len();
+        self.mounts
+            .retain(|mount| mount.prefix.trim_end_matches('/') != prefix.trim_end_matches('/'));
+        self.mounts.len() != len
+    }
+
+    /// Returns the URL prefixes of the mounts.
+    pub fn prefixes(&self) -> Vec<String> {
+        self.mounts
+            .iter()
+            .map(|mount| mount.prefix.clone())
+            .collect()
+    }
+
+    pub fn mounts(&self) -> &[Mount] {
+        &self.mounts
+    }
+
+    /// Returns the mount serving |url| and the resource path relative to it.
+    pub fn resolve(&self, url: &str) -> Option<(&Mount, String)> {
+        let url = get_url_without_query_or_fragment(url);
+        self.mounts.iter().find_map(|mount| {
+            let resource_path = url.strip_prefix(mount.prefix.as_str())?;
+            Some((mount, resource_path.to_string()))
+        })
+    }
+
+    pub fn resource_handler(&self, url: &str) -> Option<ResourceHandler> {
+        let (mount, resource_path) = self.resolve(url)?;
+        mount.resource_handler(&resource_path)
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::{Arc, Mutex};
+
+use cef::{wrapper::resource_manager::*, *};
+
+use crate::{
+    shared::resource_mount::{Mount, MountSource, MountTable},
+    tests_shared::resource_util::create_binary_resource_provider,
+};
+
+/// Order of generated content. Providers with a lower order are asked first.
+pub const CONTENT_ORDER: i32 = 0;
+/// Order of the first mount. Mounts are added in the order of `MountTable::mounts`.
+pub const MOUNT_ORDER: i32 = 100;
+/// Order for providers that should only answer requests no other provider handled,
+/// e.g. the binary resources of the bundle mounts. Requests to a mount that nobody
+/// answers are served with a 404 page by `ClientManager::resource_handler`.
+pub const FALLBACK_ORDER: i32 = 1000;
+
+const CONTENT_IDENTIFIER: &str = "content";
+const MOUNT_IDENTIFIER: &str = "mount";
+const FALLBACK_IDENTIFIER: &str = "fallback";
+
+/// Rewrites request URLs before they are matched against the providers.
+pub type UrlFilter = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Returns the providers serving |mount_table| with their order and identifier.
+fn mount_providers(
+    mount_table: &MountTable,
+) -> Vec<(Box<dyn ResourceManagerProvider>, i32, &'static str)> {
+    let mut providers = Vec::new();
+    for (i, mount) in mount_table.mounts().iter().enumerate() {
+        // Bundled files are served through the mount as well, so that they are
+        // streamed from disk with range support where the platform allows it.
+        let provider: Box<dyn ResourceManagerProvider> = Box::new(MountProvider {
+            mount: mount.clone(),
+        });
+        providers.push((provider, MOUNT_ORDER + i as i32, MOUNT_IDENTIFIER));
+
+        // Binary resources the mount doesn't find as files, e.g. Windows resources.
+        if let MountSource::Bundle(prefix) = &mount.source {
+            providers.push((
+                create_binary_resource_provider(&mount.prefix, prefix),
+                FALLBACK_ORDER + i as i32,
+                FALLBACK_IDENTIFIER,
+            ));
+        }
+    }
+    providers
+}
+
+/// Serves resources with a `ResourceManager` whose providers are asked in order:
+/// generated content, the mounts, and finally any fallback providers.
+pub struct ResourcePipeline {
+    resource_manager: Arc<ResourceManager>,
+}
+
+impl ResourcePipeline {
+    pub fn new(mount_table: &MountTable) -> Self {
+        let pipeline = Self {
+            resource_manager: Arc::new(ResourceManager::new()),
+        };
+        pipeline.set_mounts(mount_table);
+        pipeline
+    }
+
+    /// Replaces the providers of the mounts. Content, custom providers and the URL
+    /// filter are kept.
+    pub fn set_mounts(&self, mount_table: &MountTable) {
+        self.resource_manager.remove_providers(MOUNT_IDENTIFIER);
+        self.resource_manager.remove_providers(FALLBACK_IDENTIFIER);
+        for (provider, order, identifier) in mount_providers(mount_table) {
+            self.resource_manager
+                .add_provider(provider, order, identifier);
+        }
+    }
+
+    /// Serves |content| for requests to exactly |url|.
+    pub fn add_content(&self, url: &str, content: &str, mime_type: &str) {
+        self.resource_manager.add_content_provider(
+            url,
+            content,
+            mime_type,
+            CONTENT_ORDER,
+            CONTENT_IDENTIFIER,
+        );
+    }
+
+    /// Adds a custom provider. |identifier| can be used to remove it again.
+    pub fn add_provider(
+        &self,
+        provider: Box<dyn ResourceManagerProvider>,
+        order: i32,
+        identifier: &str,
+    ) {
+        self.resource_manager
+            .add_provider(provider, order, identifier);
+    }
+
+    pub fn remove_providers(&self, identifier: &str) {
+        self.resource_manager.remove_providers(identifier);
+    }
+
+    /// Sets the filter applied to request URLs. The default filter removes the
+    /// query and fragment components.
+    pub fn set_url_filter(&self, filter: UrlFilter) {
+        self.resource_manager.set_url_filter(filter);
+    }
+
+    // CefResourceRequestHandler method
+    pub fn on_before_resource_load(
+        &self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        request: Option<Request>,
+        callback: Option<Callback>,
+    ) -> ReturnValue {
+        self.resource_manager
+            .on_before_resource_load(browser, frame, request, callback)
+    }
+
+    // CefResourceRequestHandler method
+    pub fn resource_handler(
+        &self,
+        browser: Option<Browser>,
+        frame: Option<Frame>,
+        request: Option<Request>,
+    ) -> Option<ResourceHandler> {
+        self.resource_manager
+            .get_resource_handler(browser, frame, request)
+    }
+}
+
+/// Serves the resources of a mount.
+struct MountProvider {
+    mount: Mount,
+}
+
+impl ResourceManagerProvider for MountProvider {
+    fn on_request(&self, request: Arc<Mutex<ResourceManagerRequest>>) -> bool {
+        debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+        let Ok(mut request) = request.lock() else {
+            return false;
+        };
+        let url = request.url().to_string();
+        let Some(resource_path) = url.strip_prefix(self.mount.prefix.as_str()) else {
+            // Not handled by this provider.
+            return false;
+        };
+
+        let Some(handler) = self.mount.resource_handler(resource_path) else {
+            // Let the next provider try.
+            return false;
+        };
+
+        request.continue_request(Some(handler));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_providers_keep_to_their_identifiers() {
+        let mut mount_table = MountTable::default();
+        mount_table.mount("app://main/", MountSource::Bundle("app/".to_string()));
+        mount_table.mount("app://data/", MountSource::Directory("/tmp".into()));
+
+        let providers = mount_providers(&mount_table);
+        let mut registrations = providers
+            .iter()
+            .map(|(_, order, identifier)| (*order, *identifier))
+            .collect::<Vec<_>>();
+        registrations.sort();
+        assert_eq!(
+            registrations,
+            [
+                (MOUNT_ORDER, MOUNT_IDENTIFIER),
+                (MOUNT_ORDER + 1, MOUNT_IDENTIFIER),
+                (MOUNT_ORDER + 2, MOUNT_IDENTIFIER),
+                (FALLBACK_ORDER, FALLBACK_IDENTIFIER),
+                (FALLBACK_ORDER + 1, FALLBACK_IDENTIFIER),
+            ]
+        );
+        // `set_mounts` only removes these, so content and custom providers survive.
+        assert!(
+            providers
+                .iter()
+                .all(|(_, _, identifier)| *identifier != CONTENT_IDENTIFIER)
+        );
+    }
+}
@@ -1,14 +1,30 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
 use cef::{
     ResourceHandler,
-    wrapper::{resource_manager::get_mime_type, stream_resource_handler::StreamResourceHandler},
+    wrapper::{
+        byte_read_handler::{ByteReadHandler, ByteStream},
+        resource_manager::get_mime_type,
+        stream_resource_handler::StreamResourceHandler,
+    },
+    *,
 };
 
-use crate::tests_shared::resource_util::get_binary_resource_reader;
+use crate::{
+    shared::file_resource_handler::create_file_resource_handler,
+    tests_shared::resource_util::get_binary_resource_reader,
+};
 
 pub const TEST_ORIGIN: &str = "https://example.com/";
+pub const CRASH_PAGE_PATH: &str = "crash.html";
+pub const CERTIFICATE_ERROR_PAGE_PATH: &str = "certificate-error.html";
 
 /// Returns |url| without the query or fragment components, if any.
-fn get_url_without_query_or_fragment(url: &str) -> String {
+pub fn get_url_without_query_or_fragment(url: &str) -> String {
     // Find the first instance of '?' or '#'.
     let q_pos = url.find('?').unwrap_or(url.len());
     let f_pos = url.find('#').unwrap_or(url.len());
@@ -17,16 +33,137 @@ fn get_url_without_query_or_fragment(url: &str) -> String {
     url[..pos].to_string()
 }
 
+/// Returns the normalized resource path of |url| relative to `TEST_ORIGIN`, see
+/// `normalize_resource_path`.
 pub fn get_resource_path(url: &str) -> Option<String> {
     if !url.starts_with(TEST_ORIGIN) {
         return None;
     }
 
     let url_no_query = get_url_without_query_or_fragment(url);
-    Some(url_no_query[TEST_ORIGIN.len()..].to_string())
+    normalize_resource_path(&url_no_query[TEST_ORIGIN.len()..])
+}
+
+/// Decodes the `%XX` escapes of a URL path. Returns `None` if an escape is
+/// malformed or the result isn't valid UTF-8.
+pub fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Turns the URL path |resource_path| into a relative file path that can safely be
+/// joined to a resource root.
+///
+/// The path is percent-decoded and then cleaned up by `clean_resource_path`.
+/// Decoding happens only here: the result must not be decoded again, otherwise
+/// `%252e` would turn into `.`.
+pub fn normalize_resource_path(resource_path: &str) -> Option<String> {
+    clean_resource_path(&percent_decode(resource_path)?)
+}
+
+/// Cleans up the already decoded path |decoded| without decoding it again. Empty
+/// and `.` segments are dropped and `..` segments remove their parent. Returns
+/// `None` if the path would escape the root, or contains a NUL, a backslash or a
+/// component that isn't a plain file name on this platform (e.g. a drive prefix on
+/// Windows). A trailing `/` is kept.
+pub fn clean_resource_path(decoded: &str) -> Option<String> {
+    if decoded.contains(['\\', '\0']) {
+        return None;
+    }
+
+    let mut segments = Vec::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    let mut normalized = segments.join("/");
+    if !Path::new(&normalized)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    if !normalized.is_empty() && decoded.ends_with('/') {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// How symbolic links below a resource root are treated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Never serve a file if its path contains a symbolic link.
+    Deny,
+    /// Follow symbolic links as long as the target stays below the root.
+    #[default]
+    WithinRoot,
+    /// Follow symbolic links anywhere.
+    Follow,
+}
+
+/// Returns the path of the existing file |resource_path| below |root|, or `None`
+/// if there is no such file or it lies outside of |root|. |resource_path| is
+/// already decoded (see `normalize_resource_path`) and is used literally.
+pub fn join_resource_path(
+    root: &Path,
+    resource_path: &str,
+    symlinks: SymlinkPolicy,
+) -> Option<PathBuf> {
+    let resource_path = clean_resource_path(resource_path)?;
+    let path = root.join(&resource_path);
+    if !path.is_file() {
+        return None;
+    }
+
+    match symlinks {
+        SymlinkPolicy::Follow => {}
+        SymlinkPolicy::WithinRoot => {
+            let root = fs::canonicalize(root).ok()?;
+            if !fs::canonicalize(&path).ok()?.starts_with(root) {
+                return None;
+            }
+        }
+        SymlinkPolicy::Deny => {
+            let mut current = root.to_path_buf();
+            for segment in resource_path
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+            {
+                current.push(segment);
+                if fs::symlink_metadata(&current).ok()?.is_symlink() {
+                    return None;
+                }
+            }
+        }
+    }
+    Some(path)
 }
 
 pub fn get_resource_handler(resource_path: &str) -> Option<ResourceHandler> {
+    // Resources that exist as files are streamed from disk with range support.
+    #[cfg(not(target_os = "windows"))]
+    if let Some(path) = crate::tests_shared::resource_util::get_resource_file_path(resource_path) {
+        return get_file_resource_handler(&path, resource_path);
+    }
+
     let reader = get_binary_resource_reader(resource_path)?;
 
     Some(StreamResourceHandler::new_with_stream(
@@ -34,3 +171,177 @@ pub fn get_resource_handler(resource_path: &str) -> Option<ResourceHandler> {
         reader,
     ))
 }
+
+/// Returns a handler serving the file at |path|. |resource_path| is used to determine
+/// the mime type.
+pub fn get_file_resource_handler(path: &Path, resource_path: &str) -> Option<ResourceHandler> {
+    if !path.is_file() {
+        return None;
+    }
+
+    Some(create_file_resource_handler(
+        path.to_path_buf(),
+        get_mime_type(resource_path),
+    ))
+}
+
+/// Returns the bytes of the post data of |request|. File elements are skipped.
+pub fn get_post_data_bytes(request: &Request) -> Vec<u8> {
+    let mut data = Vec::new();
+    let Some(post_data) = request.post_data() else {
+        return data;
+    };
+
+    let mut elements = Vec::with_capacity(post_data.element_count());
+    post_data.elements(Some(&mut elements));
+    for element in elements.into_iter().flatten() {
+        let size = element.bytes_count();
+        if size == 0 {
+            continue;
+        }
+        let offset = data.len();
+        data.resize(offset + size, 0);
+        let read = element.bytes(size, data[offset..].as_mut_ptr().cast());
+        data.truncate(offset + read);
+    }
+    data
+}
+
+/// Returns a handler serving |data| from memory.
+pub fn get_data_resource_handler(mime_type: &str, data: Vec<u8>) -> Option<ResourceHandler> {
+    let stream = ByteStream::new(data);
+    let mut handler = ByteReadHandler::new(Arc::new(Mutex::new(stream)));
+    let reader = stream_reader_create_for_handler(Some(&mut handler))?;
+
+    Some(StreamResourceHandler::new_with_stream(
+        mime_type.to_string(),
+        reader,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_without_query_or_fragment() {
+        let url = "https://example.com/index.html";
+        assert_eq!(get_url_without_query_or_fragment(url), url);
+        assert_eq!(
+            get_url_without_query_or_fragment("https://example.com/a?b=c#d"),
+            "https://example.com/a"
+        );
+        assert_eq!(
+            get_url_without_query_or_fragment("https://example.com/a#d?b=c"),
+            "https://example.com/a"
+        );
+        assert_eq!(get_url_without_query_or_fragment("?#"), "");
+    }
+
+    #[test]
+    fn resource_path() {
+        assert_eq!(
+            get_resource_path("https://example.com/dir/index.html?x=1").as_deref(),
+            Some("dir/index.html")
+        );
+        assert_eq!(
+            get_resource_path("https://example.com/a/./b//c/").as_deref(),
+            Some("a/b/c/")
+        );
+        assert_eq!(
+            get_resource_path("https://example.com/a/../b.txt").as_deref(),
+            Some("b.txt")
+        );
+        assert_eq!(
+            get_resource_path("https://example.com/my%20file.txt").as_deref(),
+            Some("my file.txt")
+        );
+        assert_eq!(get_resource_path("https://example.org/index.html"), None);
+        assert_eq!(
+            get_resource_path("https://example.com.evil/index.html"),
+            None
+        );
+    }
+
+    #[test]
+    fn resource_path_rejects_traversal() {
+        for url in [
+            "https://example.com/../etc/passwd",
+            "https://example.com/a/../../etc/passwd",
+            "https://example.com/%2e%2e/etc/passwd",
+            "https://example.com/a%2f..%2f..%2fetc/passwd",
+            "https://example.com/..%5cetc%5cpasswd",
+            "https://example.com/index.html%00.png",
+            "https://example.com/%zz",
+            "https://example.com/%e2%28",
+        ] {
+            assert_eq!(get_resource_path(url), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn normalize_absolute_components() {
+        // Leading slashes can't turn the path into an absolute one.
+        assert_eq!(
+            normalize_resource_path("//etc/passwd").as_deref(),
+            Some("etc/passwd")
+        );
+        assert_eq!(
+            normalize_resource_path("%2fetc/passwd").as_deref(),
+            Some("etc/passwd")
+        );
+        assert_eq!(normalize_resource_path("").as_deref(), Some(""));
+
+        #[cfg(target_os = "windows")]
+        assert_eq!(normalize_resource_path("C:/Windows/win.ini"), None);
+    }
+
+    #[test]
+    fn resource_path_decodes_once() {
+        assert_eq!(
+            normalize_resource_path("%252eenv").as_deref(),
+            Some("%2eenv")
+        );
+        assert_eq!(
+            normalize_resource_path("50%25off.png").as_deref(),
+            Some("50%off.png")
+        );
+
+        let root = std::env::temp_dir().join(format!("resource_decode_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join(".env"), "secret").unwrap();
+        fs::write(root.join("50%off.png"), "png").unwrap();
+
+        let join = |path: &str| {
+            let path = normalize_resource_path(path).unwrap();
+            join_resource_path(&root, &path, SymlinkPolicy::Follow)
+        };
+        assert_eq!(join("%252eenv"), None);
+        assert_eq!(join("50%25off.png"), Some(root.join("50%off.png")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn join_resource_path_symlinks() {
+        let dir = std::env::temp_dir().join(format!("resource_util_{}", std::process::id()));
+        let root = dir.join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("inside.txt"), "inside").unwrap();
+        fs::write(dir.join("outside.txt"), "outside").unwrap();
+        std::os::unix::fs::symlink(root.join("inside.txt"), root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside.txt"), root.join("escape.txt")).unwrap();
+
+        let join = |path, symlinks| join_resource_path(&root, path, symlinks).is_some();
+        assert!(join("inside.txt", SymlinkPolicy::Deny));
+        assert!(!join("link.txt", SymlinkPolicy::Deny));
+        assert!(join("link.txt", SymlinkPolicy::WithinRoot));
+        assert!(!join("escape.txt", SymlinkPolicy::WithinRoot));
+        assert!(join("escape.txt", SymlinkPolicy::Follow));
+        assert!(!join("../outside.txt", SymlinkPolicy::Follow));
+        assert!(!join("missing.txt", SymlinkPolicy::Follow));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+    time::{Duration, SystemTime},
+};
+
+use cef::*;
+
+/// How often watched directories are checked for changes.
+pub const RESOURCE_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Returns the directory to serve the resources from during development, from
+/// `--dev-resources[=<directory>]`. Without a value the `resources` directory of
+/// the source tree is used, so edits show up without a rebuild.
+pub fn get_dev_resource_dir(command_line: &CommandLine) -> Option<PathBuf> {
+    let switch = CefString::from("dev-resources");
+    if command_line.has_switch(Some(&switch)) == 0 {
+        return None;
+    }
+
+    let directory = CefString::from(&command_line.switch_value(Some(&switch))).to_string();
+    if !directory.is_empty() {
+        return Some(directory.into());
+    }
+
+    let platform = if cfg!(target_os = "windows") {
+        "win"
+    } else if cfg!(target_os = "macos") {
+        "mac"
+    } else {
+        "linux"
+    };
+    Some(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources")
+            .join(platform),
+    )
+}
+
+type Snapshot = HashMap<PathBuf, (SystemTime, u64)>;
+
+/// Records the modification time and size of every file below |dir|.
+fn snapshot(dir: &Path, files: &mut Snapshot) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            snapshot(&entry.path(), files);
+        } else {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.insert(entry.path(), (modified, metadata.len()));
+        }
+    }
+}
+
+/// Polls directories for changes on a background thread. The thread stops when
+/// the watcher is dropped; dropping doesn't wait for it, so it is safe to drop the
+/// watcher on the UI thread or while its owner is locked.
+pub struct ResourceWatcher {
+    // Disconnected when the watcher is dropped, which also wakes the thread up.
+    _stop: mpsc::Sender<()>,
+}
+
+impl ResourceWatcher {
+    /// Watches the |directories| of the mounts with the given prefixes and calls
+    /// |on_change| with the prefixes of the mounts whose files were added, removed
+    /// or modified. |on_change| is called on the watcher thread.
+    pub fn start(
+        directories: Vec<(String, PathBuf)>,
+        interval: Duration,
+        on_change: impl Fn(Vec<String>) + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        std::thread::spawn(move || {
+            let take_snapshots = || {
+                directories
+                    .iter()
+                    .map(|(_, directory)| {
+                        let mut files = Snapshot::new();
+                        snapshot(directory, &mut files);
+                        files
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let mut snapshots = take_snapshots();
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let current = take_snapshots();
+                let changed = directories
+                    .iter()
+                    .zip(snapshots.iter().zip(current.iter()))
+                    .filter(|(_, (old, new))| old != new)
+                    .map(|((prefix, _), _)| prefix.clone())
+                    .collect::<Vec<_>>();
+                snapshots = current;
+
+                if !changed.is_empty() && stopped.try_recv() == Err(TryRecvError::Empty) {
+                    on_change(changed);
+                }
+            }
+        });
+
+        Self { _stop: stop }
+    }
+}
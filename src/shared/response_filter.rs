@@ -0,0 +1,395 @@
+use std::{
+    os::raw::{c_int, c_void},
+    sync::{Arc, Mutex},
+};
+
+use cef::*;
+
+use crate::shared::request_rules::matches_pattern;
+
+/// Transforms a response body chunk by chunk.
+pub trait StreamFilter: Send {
+    /// Filters the next chunk of the body, appending the result to |output|.
+    fn process(&mut self, input: &[u8], output: &mut Vec<u8>);
+    /// Called once at the end of the body to flush buffered data.
+    fn finish(&mut self, output: &mut Vec<u8>);
+}
+
+/// Creates the filter for one response.
+pub type StreamFilterFactory = Arc<dyn Fn() -> Box<dyn StreamFilter> + Send + Sync>;
+
+/// How far into an HTML page `<head>` is looked for before the script is
+/// injected at the start of the page instead.
+const MAX_HEAD_OFFSET: usize = 64 * 1024;
+
+/// Injects a script into HTML pages right after the `<head>` tag, or after the
+/// doctype if the page has no head. Only the start of the page is held back.
+pub fn inject_script(script: &str) -> StreamFilterFactory {
+    let tag = format!("<script>{script}</script>");
+    Arc::new(move || {
+        Box::new(ScriptInjector {
+            tag: tag.clone(),
+            buffer: Some(Vec::new()),
+        })
+    })
+}
+
+struct ScriptInjector {
+    tag: String,
+    /// The start of the page until the script is injected.
+    buffer: Option<Vec<u8>>,
+}
+
+impl ScriptInjector {
+    /// Returns the offset after the `<head>` tag, if it was seen.
+    fn head_end(buffer: &[u8]) -> Option<usize> {
+        let lower = buffer.to_ascii_lowercase();
+        let start = lower
+            .windows(5)
+            .position(|window| window == b"<head")
+            .filter(|start| {
+                // Don't match `<header>`.
+                lower
+                    .get(start + 5)
+                    .is_some_and(|c| *c == b'>' || c.is_ascii_whitespace())
+            })?;
+        let end = lower[start..].iter().position(|c| *c == b'>')?;
+        Some(start + end + 1)
+    }
+
+    /// Returns the offset after the doctype, 0 if there is none.
+    fn doctype_end(buffer: &[u8]) -> usize {
+        let lower = buffer.to_ascii_lowercase();
+        let trimmed = lower.iter().take_while(|c| c.is_ascii_whitespace()).count();
+        if !lower[trimmed..].starts_with(b"<!doctype") {
+            return 0;
+        }
+        lower[trimmed..]
+            .iter()
+            .position(|c| *c == b'>')
+            .map_or(0, |end| trimmed + end + 1)
+    }
+
+    fn inject(&mut self, at: usize, output: &mut Vec<u8>) {
+        let Some(buffer) = self.buffer.take() else {
+            return;
+        };
+        output.extend_from_slice(&buffer[..at]);
+        output.extend_from_slice(self.tag.as_bytes());
+        output.extend_from_slice(&buffer[at..]);
+    }
+}
+
+impl StreamFilter for ScriptInjector {
+    fn process(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        let Some(buffer) = self.buffer.as_mut() else {
+            output.extend_from_slice(input);
+            return;
+        };
+        buffer.extend_from_slice(input);
+
+        if let Some(at) = Self::head_end(buffer) {
+            self.inject(at, output);
+        } else if buffer.len() > MAX_HEAD_OFFSET {
+            let at = Self::doctype_end(buffer);
+            self.inject(at, output);
+        }
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        if let Some(buffer) = self.buffer.as_ref() {
+            let at = Self::doctype_end(buffer);
+            self.inject(at, output);
+        }
+    }
+}
+
+/// Replaces every occurrence of |from| with |to|. Only `from.len() - 1` bytes are
+/// held back between chunks.
+pub fn replace_text(from: &str, to: &str) -> StreamFilterFactory {
+    let (from, to) = (from.as_bytes().to_vec(), to.as_bytes().to_vec());
+    Arc::new(move || {
+        Box::new(TextReplacer {
+            from: from.clone(),
+            to: to.clone(),
+            pending: Vec::new(),
+        })
+    })
+}
+
+struct TextReplacer {
+    from: Vec<u8>,
+    to: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl StreamFilter for TextReplacer {
+    fn process(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        self.pending.extend_from_slice(input);
+        if self.from.is_empty() {
+            output.append(&mut self.pending);
+            return;
+        }
+
+        let mut start = 0;
+        while let Some(pos) = self.pending[start..]
+            .windows(self.from.len())
+            .position(|window| window == self.from.as_slice())
+        {
+            output.extend_from_slice(&self.pending[start..start + pos]);
+            output.extend_from_slice(&self.to);
+            start += pos + self.from.len();
+        }
+
+        // Keep a possible partial match for the next chunk.
+        let keep = (self.from.len() - 1).min(self.pending.len() - start);
+        let end = self.pending.len() - keep;
+        output.extend_from_slice(&self.pending[start..end]);
+        self.pending.drain(..end);
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        output.append(&mut self.pending);
+    }
+}
+
+/// Rewrites the complete body with |rewrite|, e.g. to modify JSON responses for
+/// testing. Unlike the other filters this holds back the whole body.
+pub fn rewrite_body(
+    rewrite: impl Fn(Vec<u8>) -> Vec<u8> + Send + Sync + 'static,
+) -> StreamFilterFactory {
+    let rewrite = Arc::new(rewrite);
+    Arc::new(move || {
+        Box::new(BodyRewriter {
+            rewrite: rewrite.clone(),
+            body: Vec::new(),
+        })
+    })
+}
+
+struct BodyRewriter {
+    rewrite: Arc<dyn Fn(Vec<u8>) -> Vec<u8> + Send + Sync>,
+    body: Vec<u8>,
+}
+
+impl StreamFilter for BodyRewriter {
+    fn process(&mut self, input: &[u8], _output: &mut Vec<u8>) {
+        self.body.extend_from_slice(input);
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        output.extend((self.rewrite)(std::mem::take(&mut self.body)));
+    }
+}
+
+struct ResponseFilterRule {
+    pattern: String,
+    /// Mime types the filter applies to, all if empty.
+    mime_types: Vec<String>,
+    factory: StreamFilterFactory,
+}
+
+/// Selects the filters applied to response bodies by URL pattern and mime type.
+#[derive(Default)]
+pub struct ResponseFilters {
+    rules: Vec<ResponseFilterRule>,
+}
+
+impl ResponseFilters {
+    /// Filters the responses to URLs matching |pattern| (see `matches_pattern`)
+    /// whose mime type is one of |mime_types|, or any if empty.
+    pub fn add(&mut self, pattern: &str, mime_types: &[&str], factory: StreamFilterFactory) {
+        self.rules.push(ResponseFilterRule {
+            pattern: pattern.to_string(),
+            mime_types: mime_types
+                .iter()
+                .map(|mime_type| mime_type.to_string())
+                .collect(),
+            factory,
+        });
+    }
+
+    /// Removes the filters for |pattern|. Returns false if there were none.
+    pub fn remove(&mut self, pattern: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.pattern != pattern);
+        self.rules.len() != len
+    }
+
+    /// Returns the filter for the response to |url| with |mime_type|. If several
+    /// rules match, their filters are applied in the order they were added.
+    pub fn response_filter(&self, url: &str, mime_type: &str) -> Option<ResponseFilter> {
+        let filters = self
+            .rules
+            .iter()
+            .filter(|rule| {
+                matches_pattern(&rule.pattern, url)
+                    && (rule.mime_types.is_empty()
+                        || rule
+                            .mime_types
+                            .iter()
+                            .any(|candidate| candidate.eq_ignore_ascii_case(mime_type)))
+            })
+            .map(|rule| (rule.factory)())
+            .collect::<Vec<_>>();
+        if filters.is_empty() {
+            return None;
+        }
+
+        let state = FilterState {
+            filters,
+            pending: Vec::new(),
+            finished: false,
+        };
+        Some(ResponseFilterImpl::new(Arc::new(Mutex::new(state))))
+    }
+}
+
+struct FilterState {
+    filters: Vec<Box<dyn StreamFilter>>,
+    /// Filtered data that didn't fit into the output buffer yet.
+    pending: Vec<u8>,
+    finished: bool,
+}
+
+impl FilterState {
+    /// Runs |input| through all filters, finishing them at the end of the body.
+    fn process(&mut self, input: &[u8], end: bool) {
+        let mut data = input.to_vec();
+        for filter in &mut self.filters {
+            let mut output = Vec::new();
+            filter.process(&data, &mut output);
+            if end {
+                filter.finish(&mut output);
+            }
+            data = output;
+        }
+        self.pending.extend(data);
+    }
+}
+
+wrap_response_filter! {
+    struct ResponseFilterImpl {
+        state: Arc<Mutex<FilterState>>,
+    }
+
+    impl ResponseFilter {
+        fn init_filter(&self) -> c_int {
+            1
+        }
+
+        fn filter(
+            &self,
+            data_in: *mut c_void,
+            data_in_size: usize,
+            data_in_read: Option<&mut usize>,
+            data_out: *mut c_void,
+            data_out_size: usize,
+            data_out_written: Option<&mut usize>,
+        ) -> ResponseFilterStatus {
+            let mut state = self.state.lock().expect("Failed to lock state");
+
+            // All input is consumed, output that doesn't fit is kept for later calls.
+            let input = if data_in.is_null() || data_in_size == 0 {
+                &[][..]
+            } else {
+                // SAFETY: CEF provides |data_in_size| bytes at |data_in|.
+                unsafe { std::slice::from_raw_parts(data_in.cast::<u8>(), data_in_size) }
+            };
+            let end = input.is_empty();
+            if !state.finished {
+                state.process(input, end);
+                state.finished = end;
+            }
+            if let Some(data_in_read) = data_in_read {
+                *data_in_read = input.len();
+            }
+
+            let size = state.pending.len().min(data_out_size);
+            if size > 0 {
+                // SAFETY: CEF provides a buffer of at least |data_out_size| bytes.
+                unsafe {
+                    std::ptr::copy_nonoverlapping(state.pending.as_ptr(), data_out.cast::<u8>(), size);
+                }
+                state.pending.drain(..size);
+            }
+            if let Some(data_out_written) = data_out_written {
+                *data_out_written = size;
+            }
+
+            if state.finished && state.pending.is_empty() {
+                ResponseFilterStatus::DONE
+            } else {
+                ResponseFilterStatus::NEED_MORE_DATA
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs |chunks| through a filter of |factory| and returns the result.
+    fn filter(factory: &StreamFilterFactory, chunks: &[&str]) -> String {
+        let mut filter = factory();
+        let mut output = Vec::new();
+        for chunk in chunks {
+            filter.process(chunk.as_bytes(), &mut output);
+        }
+        filter.finish(&mut output);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn inject_script_after_head() {
+        let factory = inject_script("x()");
+        assert_eq!(
+            filter(&factory, &["<html><head><title>t</title></head></html>"]),
+            "<html><head><script>x()</script><title>t</title></head></html>"
+        );
+        // The `<head>` tag is split across chunks.
+        assert_eq!(
+            filter(&factory, &["<html><he", "ad lang=\"en", "\">body</html>"]),
+            "<html><head lang=\"en\"><script>x()</script>body</html>"
+        );
+        assert_eq!(
+            filter(&factory, &["<HTML><HEAD>", "</HEAD></HTML>"]),
+            "<HTML><HEAD><script>x()</script></HEAD></HTML>"
+        );
+    }
+
+    #[test]
+    fn inject_script_without_head() {
+        let factory = inject_script("x()");
+        assert_eq!(
+            filter(&factory, &["<!DOCTYPE html>", "<header>h</header>"]),
+            "<!DOCTYPE html><script>x()</script><header>h</header>"
+        );
+        assert_eq!(
+            filter(&factory, &["<p>text</p>"]),
+            "<script>x()</script><p>text</p>"
+        );
+
+        // Pages without a head are only held back up to `MAX_HEAD_OFFSET`.
+        let mut injector = factory();
+        let mut output = Vec::new();
+        injector.process("a".repeat(MAX_HEAD_OFFSET + 1).as_bytes(), &mut output);
+        assert!(output.starts_with(b"<script>x()</script>a"));
+        output.clear();
+        injector.process(b"<head>", &mut output);
+        assert_eq!(output, b"<head>");
+    }
+
+    #[test]
+    fn replace_text_across_chunks() {
+        let factory = replace_text("needle", "pin");
+        assert_eq!(filter(&factory, &["a needle, a needle"]), "a pin, a pin");
+        // The match straddles two chunks.
+        assert_eq!(filter(&factory, &["a nee", "dle here"]), "a pin here");
+        assert_eq!(filter(&factory, &["n", "e", "e", "d", "l", "e"]), "pin");
+        // A partial match at the end of the body is passed on unchanged.
+        assert_eq!(filter(&factory, &["a nee", "dl"]), "a needl");
+        assert_eq!(filter(&replace_text("", "x"), &["ab", "c"]), "abc");
+    }
+}
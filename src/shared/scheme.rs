@@ -0,0 +1,120 @@
+use cef::*;
+
+use crate::shared::client_impl::ClientManager;
+
+/// Custom scheme used to serve the application resources.
+pub const APP_SCHEME: &str = "app";
+/// Origin of the application resources on the custom scheme.
+pub const APP_ORIGIN: &str = "app://main/";
+
+// Values from cef_scheme_options_t.
+const SCHEME_OPTION_STANDARD: i32 = 1 << 0;
+const SCHEME_OPTION_LOCAL: i32 = 1 << 1;
+const SCHEME_OPTION_DISPLAY_ISOLATED: i32 = 1 << 2;
+const SCHEME_OPTION_SECURE: i32 = 1 << 3;
+const SCHEME_OPTION_CORS_ENABLED: i32 = 1 << 4;
+const SCHEME_OPTION_CSP_BYPASSING: i32 = 1 << 5;
+const SCHEME_OPTION_FETCH_ENABLED: i32 = 1 << 6;
+
+/// Registration options of a custom scheme. See `cef_scheme_options_t` for details.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchemeOptions {
+    pub standard: bool,
+    pub local: bool,
+    pub display_isolated: bool,
+    pub secure: bool,
+    pub cors_enabled: bool,
+    pub csp_bypassing: bool,
+    pub fetch_enabled: bool,
+}
+
+impl SchemeOptions {
+    fn bits(&self) -> i32 {
+        [
+            (self.standard, SCHEME_OPTION_STANDARD),
+            (self.local, SCHEME_OPTION_LOCAL),
+            (self.display_isolated, SCHEME_OPTION_DISPLAY_ISOLATED),
+            (self.secure, SCHEME_OPTION_SECURE),
+            (self.cors_enabled, SCHEME_OPTION_CORS_ENABLED),
+            (self.csp_bypassing, SCHEME_OPTION_CSP_BYPASSING),
+            (self.fetch_enabled, SCHEME_OPTION_FETCH_ENABLED),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |bits, (_, bit)| bits | bit)
+    }
+}
+
+/// Custom schemes of the application and their options.
+pub fn get_custom_schemes() -> Vec<(&'static str, SchemeOptions)> {
+    vec![(
+        APP_SCHEME,
+        SchemeOptions {
+            standard: true,
+            secure: true,
+            cors_enabled: true,
+            fetch_enabled: true,
+            ..Default::default()
+        },
+    )]
+}
+
+/// Registers the custom schemes. Must be called from `on_register_custom_schemes`
+/// of every process with the same schemes.
+pub fn register_custom_schemes(registrar: Option<&mut SchemeRegistrar>) {
+    let Some(registrar) = registrar else {
+        return;
+    };
+
+    for (scheme, options) in get_custom_schemes() {
+        let registered =
+            registrar.add_custom_scheme(Some(&CefString::from(scheme)), options.bits());
+        debug_assert_ne!(registered, 0, "Failed to register scheme {scheme}");
+    }
+}
+
+/// Registers the handler factory of the custom schemes with |request_context|, or
+/// with the global request context if it is `None`.
+pub fn register_scheme_handler_factories(request_context: Option<&RequestContext>) {
+    for (scheme, _) in get_custom_schemes() {
+        let mut factory = SchemeHandlerFactoryImpl::new();
+        let scheme = CefString::from(scheme);
+        match request_context {
+            Some(request_context) => {
+                request_context.register_scheme_handler_factory(
+                    Some(&scheme),
+                    None,
+                    Some(&mut factory),
+                );
+            }
+            None => {
+                register_scheme_handler_factory(Some(&scheme), None, Some(&mut factory));
+            }
+        }
+    }
+}
+
+wrap_scheme_handler_factory! {
+    struct SchemeHandlerFactoryImpl {}
+
+    impl SchemeHandlerFactory {
+        fn create(
+            &self,
+            browser: Option<&mut Browser>,
+            _frame: Option<&mut Frame>,
+            _scheme_name: Option<&CefString>,
+            request: Option<&mut Request>,
+        ) -> Option<ResourceHandler> {
+            debug_assert_ne!(currently_on(ThreadId::IO), 0);
+
+            // Requests that the resource pipeline didn't handle are served from the
+            // mounts, with the same checks and headers.
+            let manager = browser
+                .as_deref()
+                .and_then(ClientManager::for_browser)
+                .or_else(ClientManager::instance)?;
+            let manager = manager.lock().expect("Failed to lock ClientManager");
+            manager.scheme_resource_handler(browser.cloned(), request.cloned())
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::sync::Arc;
+
+/// Replaced with a fresh nonce in the Content Security Policy and in HTML pages,
+/// e.g. `script-src 'nonce-{{csp-nonce}}'` and `<script nonce="{{csp-nonce}}">`.
+pub const CSP_NONCE_PLACEHOLDER: &str = "{{csp-nonce}}";
+
+/// Path of the endpoint below each mount that receives the violation reports of a
+/// report-only policy.
+pub const CSP_REPORT_PATH: &str = "__csp-report";
+
+/// A Content Security Policy violation report sent by a page.
+#[derive(Clone, Debug)]
+pub struct CspReport {
+    /// Prefix of the mount that served the page.
+    pub prefix: String,
+    /// The report as sent by the browser, usually `application/csp-report` JSON.
+    pub body: String,
+}
+
+/// Called on the UI thread with every violation report.
+pub type CspReportCallback = Arc<dyn Fn(&CspReport) + Send + Sync>;
+
+/// Security headers added to the responses of a mount.
+#[derive(Clone, Debug)]
+pub struct SecurityPolicy {
+    /// The `Content-Security-Policy`. Occurrences of `CSP_NONCE_PLACEHOLDER` are
+    /// replaced with a nonce that is unique for every response.
+    pub content_security_policy: Option<String>,
+    /// Sends the policy as `Content-Security-Policy-Report-Only` and reports
+    /// violations to `CSP_REPORT_PATH` instead of enforcing it.
+    pub report_only: bool,
+    /// Sends `X-Content-Type-Options: nosniff`.
+    pub content_type_options: bool,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        Self {
+            content_security_policy: None,
+            report_only: false,
+            content_type_options: true,
+            referrer_policy: Some("strict-origin-when-cross-origin".to_string()),
+            permissions_policy: None,
+        }
+    }
+}
+
+impl SecurityPolicy {
+    pub fn with_content_security_policy(mut self, policy: &str) -> Self {
+        self.content_security_policy = Some(policy.to_string());
+        self
+    }
+
+    pub fn with_report_only(mut self, report_only: bool) -> Self {
+        self.report_only = report_only;
+        self
+    }
+
+    pub fn with_referrer_policy(mut self, policy: Option<&str>) -> Self {
+        self.referrer_policy = policy.map(str::to_string);
+        self
+    }
+
+    pub fn with_permissions_policy(mut self, policy: Option<&str>) -> Self {
+        self.permissions_policy = policy.map(str::to_string);
+        self
+    }
+
+    /// Returns true if responses need a nonce.
+    pub fn uses_nonce(&self) -> bool {
+        self.content_security_policy
+            .as_ref()
+            .is_some_and(|policy| policy.contains(CSP_NONCE_PLACEHOLDER))
+    }
+
+    /// Returns true if violation reports are accepted for this policy.
+    pub fn accepts_reports(&self) -> bool {
+        self.report_only && self.content_security_policy.is_some()
+    }
+
+    /// Returns the headers of a response from the mount at |prefix|.
+    pub fn headers(&self, prefix: &str, nonce: Option<&str>) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+
+        if let Some(policy) = self.content_security_policy.as_ref() {
+            let mut policy = policy.replace(CSP_NONCE_PLACEHOLDER, nonce.unwrap_or_default());
+            if self.report_only {
+                policy = format!(
+                    "{}; report-uri {prefix}{CSP_REPORT_PATH}",
+                    policy.trim_end().trim_end_matches(';')
+                );
+                headers.push(("Content-Security-Policy-Report-Only".to_string(), policy));
+            } else {
+                headers.push(("Content-Security-Policy".to_string(), policy));
+            }
+        }
+        if self.content_type_options {
+            headers.push(("X-Content-Type-Options".to_string(), "nosniff".to_string()));
+        }
+        if let Some(policy) = self.referrer_policy.as_ref() {
+            headers.push(("Referrer-Policy".to_string(), policy.clone()));
+        }
+        if let Some(policy) = self.permissions_policy.as_ref() {
+            headers.push(("Permissions-Policy".to_string(), policy.clone()));
+        }
+
+        headers
+    }
+}
+
+/// Replaces `CSP_NONCE_PLACEHOLDER` in the HTML |page| with |nonce|.
+pub fn insert_nonce(page: &str, nonce: &str) -> String {
+    page.replace(CSP_NONCE_PLACEHOLDER, nonce)
+}
+
+/// Returns a random nonce for a Content Security Policy.
+pub fn generate_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("Failed to generate a nonce");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(candidate, _)| candidate == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn default_headers() {
+        let headers = SecurityPolicy::default().headers("app://main/", None);
+        assert_eq!(
+            headers,
+            [
+                ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+                (
+                    "Referrer-Policy".to_string(),
+                    "strict-origin-when-cross-origin".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn enforced_policy() {
+        let policy = SecurityPolicy::default()
+            .with_content_security_policy("script-src 'nonce-{{csp-nonce}}'")
+            .with_referrer_policy(None)
+            .with_permissions_policy(Some("camera=()"));
+        assert!(policy.uses_nonce());
+        assert!(!policy.accepts_reports());
+
+        let headers = policy.headers("app://main/", Some("abc"));
+        assert_eq!(
+            header(&headers, "Content-Security-Policy"),
+            Some("script-src 'nonce-abc'")
+        );
+        assert_eq!(
+            header(&headers, "Content-Security-Policy-Report-Only"),
+            None
+        );
+        assert_eq!(header(&headers, "Referrer-Policy"), None);
+        assert_eq!(header(&headers, "Permissions-Policy"), Some("camera=()"));
+    }
+
+    #[test]
+    fn report_only_policy() {
+        let policy = SecurityPolicy::default()
+            .with_content_security_policy("default-src 'self'; ")
+            .with_report_only(true);
+        assert!(!policy.uses_nonce());
+        assert!(policy.accepts_reports());
+
+        let headers = policy.headers("https://example.com/app/", None);
+        assert_eq!(header(&headers, "Content-Security-Policy"), None);
+        assert_eq!(
+            header(&headers, "Content-Security-Policy-Report-Only"),
+            Some("default-src 'self'; report-uri https://example.com/app/__csp-report")
+        );
+
+        // Reports need a policy to report on.
+        assert!(
+            !SecurityPolicy::default()
+                .with_report_only(true)
+                .accepts_reports()
+        );
+    }
+
+    #[test]
+    fn nonces() {
+        let nonce = generate_nonce();
+        assert_eq!(nonce.len(), 32);
+        assert!(
+            nonce
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        );
+        assert_ne!(generate_nonce(), nonce);
+    }
+
+    #[test]
+    fn nonce_placeholders() {
+        let page =
+            r#"<script nonce="{{csp-nonce}}">a()</script><script nonce="{{csp-nonce}}"></script>"#;
+        assert_eq!(
+            insert_nonce(page, "abc"),
+            r#"<script nonce="abc">a()</script><script nonce="abc"></script>"#
+        );
+        assert_eq!(insert_nonce("<p>{{csp}}</p>", "abc"), "<p>{{csp}}</p>");
+    }
+}
@@ -0,0 +1,23 @@
+// Resources baked into the binary by build.rs when the `embed-resources` feature is enabled.
+
+use cef::{
+    wrapper::byte_read_handler::{ByteReadHandler, ByteStream},
+    *,
+};
+use std::sync::{Arc, Mutex};
+
+include!(concat!(env!("OUT_DIR"), "/embedded_resources.rs"));
+
+pub fn get_embedded_resource(resource_name: &str) -> Option<&'static [u8]> {
+    EMBEDDED_RESOURCES
+        .iter()
+        .find(|(name, _)| *name == resource_name)
+        .map(|(_, data)| *data)
+}
+
+pub fn get_embedded_resource_reader(resource_name: &str) -> Option<StreamReader> {
+    let data = get_embedded_resource(resource_name)?;
+    let stream = ByteStream::new(data.to_vec());
+    let mut handler = ByteReadHandler::new(Arc::new(Mutex::new(stream)));
+    stream_reader_create_for_handler(Some(&mut handler))
+}
@@ -5,7 +5,110 @@ pub mod win;
 #[cfg(target_os = "windows")]
 pub use win::*;
 
+#[cfg(all(target_os = "linux", feature = "embed-resources"))]
+pub mod embedded;
+
 #[cfg(not(target_os = "windows"))]
 pub mod posix;
 #[cfg(not(target_os = "windows"))]
 pub use posix::*;
+
+use cef::{
+    wrapper::{resource_manager::*, stream_resource_handler::*},
+    *,
+};
+use std::sync::{Arc, Mutex};
+
+use crate::shared::resource_util::normalize_resource_path;
+
+// The provider is shared by all platforms, only `get_binary_resource_reader` is
+// platform specific.
+struct BinaryResourceProvider {
+    url_path: String,
+    resource_path_prefix: String,
+}
+
+impl BinaryResourceProvider {
+    fn new(url_path: &str, resource_path_prefix: &str) -> Self {
+        let url_path = if url_path.ends_with('/') {
+            url_path.to_string()
+        } else {
+            format!("{url_path}/")
+        };
+        Self {
+            url_path,
+            resource_path_prefix: resource_path_prefix.trim_matches('/').to_string(),
+        }
+    }
+
+    /// Returns the name of the binary resource for the request |url|, or `None` if
+    /// |url| isn't below |url_path| or escapes it.
+    fn resource_name(&self, url: &str) -> Option<String> {
+        let relative_path = normalize_resource_path(url.strip_prefix(self.url_path.as_str())?)?;
+        if self.resource_path_prefix.is_empty() {
+            Some(relative_path)
+        } else {
+            Some(format!("{}/{relative_path}", self.resource_path_prefix))
+        }
+    }
+}
+
+impl ResourceManagerProvider for BinaryResourceProvider {
+    fn on_request(&self, request: Arc<Mutex<ResourceManagerRequest>>) -> bool {
+        assert_ne!(
+            currently_on(ThreadId::IO),
+            0,
+            "on_request must be called on the IO thread"
+        );
+
+        let Ok(mut request) = request.lock() else {
+            return false;
+        };
+        let url = request.url();
+        let Some(resource_name) = self.resource_name(url) else {
+            // Not handled by this provider.
+            return false;
+        };
+
+        let mime_type = request.mime_type_resolver()(url);
+        let handler = get_binary_resource_reader(&resource_name)
+            .map(|stream| StreamResourceHandler::new_with_stream(mime_type, stream));
+
+        request.continue_request(handler);
+        true
+    }
+}
+
+/// Returns a provider serving the binary resources below |resource_path_prefix|
+/// for requests below |url_path|.
+pub fn create_binary_resource_provider(
+    url_path: &str,
+    resource_path_prefix: &str,
+) -> Box<dyn ResourceManagerProvider> {
+    Box::new(BinaryResourceProvider::new(url_path, resource_path_prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_resource_names() {
+        let provider = BinaryResourceProvider::new("https://example.com", "");
+        assert_eq!(
+            provider.resource_name("https://example.com/index.html"),
+            Some("index.html".to_string())
+        );
+        assert_eq!(
+            provider.resource_name("https://example.com/../secret"),
+            None
+        );
+        assert_eq!(provider.resource_name("https://other.com/index.html"), None);
+
+        let provider = BinaryResourceProvider::new("app://main/assets/", "/static/");
+        assert_eq!(
+            provider.resource_name("app://main/assets/js/app.js"),
+            Some("static/js/app.js".to_string())
+        );
+    }
+}
@@ -3,7 +3,24 @@
 use cef::*;
 use std::{fs::File, io::Read, path::PathBuf};
 
+use crate::shared::resource_util::{SymlinkPolicy, join_resource_path};
+
+/// Serves the resources from this directory instead of the default location. With
+/// the `embed-resources` feature this also bypasses the embedded resources, which
+/// is useful during development.
+pub const RESOURCE_DIR_ENV: &str = "MESSAGE_ROUTER_RESOURCE_DIR";
+
+/// Returns true if the resources should be read from the embedded resources.
+#[cfg(all(target_os = "linux", feature = "embed-resources"))]
+fn use_embedded_resources() -> bool {
+    std::env::var_os(RESOURCE_DIR_ENV).is_none()
+}
+
 pub fn get_resource_directory() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(RESOURCE_DIR_ENV) {
+        return Some(path.into());
+    }
+
     let mut path = std::env::current_exe().ok()?;
 
     // Pop the executable file name.
@@ -24,8 +41,27 @@ pub fn get_resource_directory() -> Option<PathBuf> {
     Some(path)
 }
 
+/// Returns the path of |resource_name| if it is served from a file on disk.
+pub fn get_resource_file_path(resource_name: &str) -> Option<PathBuf> {
+    #[cfg(all(target_os = "linux", feature = "embed-resources"))]
+    if use_embedded_resources() {
+        return None;
+    }
+
+    join_resource_path(
+        &get_resource_directory()?,
+        resource_name,
+        SymlinkPolicy::WithinRoot,
+    )
+}
+
 pub fn load_binary_resource(resource_name: &str) -> Option<Vec<u8>> {
-    let path = get_resource_directory()?.join(resource_name);
+    #[cfg(all(target_os = "linux", feature = "embed-resources"))]
+    if use_embedded_resources() {
+        return super::embedded::get_embedded_resource(resource_name).map(<[u8]>::to_vec);
+    }
+
+    let path = get_resource_file_path(resource_name)?;
     let mut file = File::open(path).ok()?;
     let mut data = Vec::new();
     file.read_to_end(&mut data).ok()?;
@@ -33,11 +69,12 @@ pub fn load_binary_resource(resource_name: &str) -> Option<Vec<u8>> {
 }
 
 pub fn get_binary_resource_reader(resource_name: &str) -> Option<StreamReader> {
-    let path = get_resource_directory()?.join(resource_name);
-    if !path.exists() {
-        return None;
+    #[cfg(all(target_os = "linux", feature = "embed-resources"))]
+    if use_embedded_resources() {
+        return super::embedded::get_embedded_resource_reader(resource_name);
     }
 
+    let path = get_resource_file_path(resource_name)?;
     let path = path.to_str()?;
     let path = CefString::from(path);
     stream_reader_create_for_file(Some(&CefString::from(path)))
@@ -1,10 +1,7 @@
 // This file is derived from here: https://github.com/tauri-apps/cef-rs/blob/c35e1d08dbd24253d314eef4bef9455e4c67e14c/examples/tests_shared/src/browser/resource_util/win.rs
 
 use crate::browser::util_win::*;
-use cef::{
-    wrapper::{byte_read_handler::*, resource_manager::*, stream_resource_handler::*},
-    *,
-};
+use cef::{wrapper::byte_read_handler::*, *};
 use std::{
     mem,
     sync::{Arc, Mutex, OnceLock},
@@ -76,51 +73,3 @@ pub fn get_binary_resource_reader(resource_name: &str) -> Option<StreamReader> {
     let mut handler = ByteReadHandler::new(Arc::new(Mutex::new(stream)));
     stream_reader_create_for_handler(Some(&mut handler))
 }
-
-struct BinaryResourceProvider {
-    url_path: String,
-    resource_path_prefix: String,
-}
-
-impl BinaryResourceProvider {
-    fn new(url_path: &str, resource_path_prefix: &str) -> Self {
-        Self {
-            url_path: normalize_url_path(url_path),
-            resource_path_prefix: normalize_url_path(resource_path_prefix),
-        }
-    }
-}
-
-impl ResourceManagerProvider for BinaryResourceProvider {
-    fn on_request(&self, request: Arc<Mutex<ResourceManagerRequest>>) -> bool {
-        assert_ne!(
-            currently_on(ThreadId::IO),
-            0,
-            "on_request must be called on the IO thread"
-        );
-
-        let Ok(mut request) = request.lock() else {
-            return false;
-        };
-        let url = request.url();
-        let Some(relative_path) = url.strip_prefix(self.url_path.as_str()) else {
-            // Not handled by this provider.
-            return false;
-        };
-
-        let mime_type = request.mime_type_resolver()(url);
-        let relative_path = format!("{}/{relative_path}", self.resource_path_prefix);
-        let handler = get_binary_resource_reader(&relative_path)
-            .map(|stream| StreamResourceHandler::new_with_stream(mime_type, stream));
-
-        request.continue_request(handler);
-        true
-    }
-}
-
-pub fn create_binary_resource_provider(
-    url_path: &str,
-    resource_path_prefix: &str,
-) -> Box<dyn ResourceManagerProvider> {
-    Box::new(BinaryResourceProvider::new(url_path, resource_path_prefix))
-}